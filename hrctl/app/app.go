@@ -18,6 +18,7 @@ var (
 	errEmptyInputDir  = "input directory cannot be empty"
 	errEmptyPayload   = "payload path cannot be empty"
 	errInvalidSepator = "cannot use undefined input separator"
+	errInvalidSample  = "sample fraction must be between 0 and 1"
 )
 
 // Run the application
@@ -41,7 +42,7 @@ func loadJob(jobPath string) (*dpb.Job, error) {
 	}
 
 	// Check required arguments
-	if job.GetInputDirectory() == "" {
+	if job.GetInputDirectory() == "" && len(job.GetTaggedInputDirectories()) == 0 {
 		return nil, errors.New(errEmptyInputDir)
 	}
 	if job.GetPayloadPath() == "" {
@@ -50,5 +51,16 @@ func loadJob(jobPath string) (*dpb.Job, error) {
 	if job.GetInputKind() == dpb.InputDataKind_UNDEFINED {
 		return nil, errors.New(errInvalidSepator)
 	}
+	if job.GetSampleFraction() < 0 || job.GetSampleFraction() > 1 {
+		return nil, errors.New(errInvalidSample)
+	}
+	for _, stage := range job.GetStages() {
+		if stage.GetPayloadPath() == "" {
+			return nil, errors.New(errEmptyPayload)
+		}
+		if stage.GetInputKind() == dpb.InputDataKind_UNDEFINED {
+			return nil, errors.New(errInvalidSepator)
+		}
+	}
 	return job, nil
 }
@@ -3,6 +3,8 @@ package app
 import (
 	"github.com/pkg/errors"
 	"github.com/urfave/cli"
+
+	dpb "github.com/cpssd/heracles/proto/datatypes"
 )
 
 func parse() *cli.App {
@@ -30,6 +32,10 @@ func parse() *cli.App {
 					Usage: "load the job configuration from `FILE`",
 					Value: "",
 				},
+				cli.StringSliceFlag{
+					Name:  "after",
+					Usage: "run the job only once job `JOB-ID` is done, can be repeated",
+				},
 			},
 			ArgsUsage: " ",
 		}, {
@@ -37,6 +43,49 @@ func parse() *cli.App {
 			Usage:     "cancel a job",
 			Action:    cancel,
 			ArgsUsage: "<job-id>",
+		}, {
+			Name:  "recurring",
+			Usage: "manage jobs which are scheduled on a cron schedule",
+			Subcommands: []cli.Command{
+				{
+					Name:   "create",
+					Usage:  "schedule a job from given input file on a cron schedule",
+					Action: createRecurringJob,
+					Flags: []cli.Flag{
+						cli.StringFlag{
+							Name:  "job_file, f",
+							Usage: "load the job configuration from `FILE`",
+							Value: "",
+						},
+						cli.StringFlag{
+							Name:  "cron",
+							Usage: "schedule the job at the times matching `EXPRESSION`, in UTC",
+							Value: "",
+						},
+					},
+					ArgsUsage: " ",
+				}, {
+					Name:      "list",
+					Usage:     "List of all recurring jobs",
+					ArgsUsage: " ",
+					Action:    listRecurringJobs,
+				}, {
+					Name:      "pause",
+					Usage:     "stop scheduling a recurring job",
+					ArgsUsage: "<RECURRING-JOB-ID>",
+					Action:    pauseRecurringJob,
+				}, {
+					Name:      "resume",
+					Usage:     "start scheduling a paused recurring job again",
+					ArgsUsage: "<RECURRING-JOB-ID>",
+					Action:    resumeRecurringJob,
+				}, {
+					Name:      "delete",
+					Usage:     "delete a recurring job",
+					ArgsUsage: "<RECURRING-JOB-ID>",
+					Action:    deleteRecurringJob,
+				},
+			},
 		}, {
 			Name:  "describe",
 			Usage: "describe a resource in heracles",
@@ -100,6 +149,7 @@ func schedule(c *cli.Context) error {
 	if err != nil {
 		return errors.Wrap(err, "unable to get job from file")
 	}
+	job.DependsOn = append(job.DependsOn, c.StringSlice("after")...)
 
 	conn, err := connect(c.GlobalString("manager"))
 	if err != nil {
@@ -123,6 +173,62 @@ func cancel(c *cli.Context) error {
 	return conn.cancel(jobID)
 }
 
+func createRecurringJob(c *cli.Context) error {
+	job, err := loadJob(c.String("job_file"))
+	if err != nil {
+		return errors.Wrap(err, "unable to get job from file")
+	}
+	if c.String("cron") == "" {
+		return errors.New("cron expression cannot be empty")
+	}
+
+	conn, err := connect(c.GlobalString("manager"))
+	if err != nil {
+		return errors.Wrap(err, "unable to connect to manager")
+	}
+
+	return conn.createRecurringJob(&dpb.RecurringJob{
+		CronExpression: c.String("cron"),
+		JobTemplate:    job,
+	})
+}
+
+func listRecurringJobs(c *cli.Context) error {
+	conn, err := connect(c.GlobalString("manager"))
+	if err != nil {
+		return errors.Wrap(err, "unable to connect to manager")
+	}
+
+	return conn.listRecurringJobs()
+}
+
+func pauseRecurringJob(c *cli.Context) error {
+	return updateRecurringJob(c, (*conn).pauseRecurringJob)
+}
+
+func resumeRecurringJob(c *cli.Context) error {
+	return updateRecurringJob(c, (*conn).resumeRecurringJob)
+}
+
+func deleteRecurringJob(c *cli.Context) error {
+	return updateRecurringJob(c, (*conn).deleteRecurringJob)
+}
+
+// updateRecurringJob runs update on the recurring job given as the first argument.
+func updateRecurringJob(c *cli.Context, update func(*conn, string) error) error {
+	conn, err := connect(c.GlobalString("manager"))
+	if err != nil {
+		return errors.Wrap(err, "unable to connect to manager")
+	}
+
+	id := c.Args().First()
+	if id == "" {
+		return errors.New("recurring job ID cannot be empty")
+	}
+
+	return update(conn, id)
+}
+
 func describeCluster(c *cli.Context) error {
 	conn, err := connect(c.String("manager"))
 	if err != nil {
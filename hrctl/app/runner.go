@@ -4,6 +4,7 @@ import (
 	"context"
 	"fmt"
 	"os"
+	"strings"
 	"time"
 
 	"github.com/golang/protobuf/proto"
@@ -58,6 +59,85 @@ func (c *conn) cancel(jobID string) error {
 	return nil
 }
 
+func (c *conn) createRecurringJob(recurringJob *dpb.RecurringJob) error {
+	ctx, cancel := context.WithTimeout(context.Background(), 1*time.Second)
+	defer cancel()
+
+	res, err := c.CreateRecurringJob(ctx, &pb.CreateRecurringJobRequest{RecurringJob: recurringJob})
+	if err != nil {
+		return errors.Wrap(err, "unable to create recurring job")
+	}
+
+	fmt.Println("Recurring job", res.GetRecurringJobId(), "successfully created")
+	return nil
+}
+
+func (c *conn) listRecurringJobs() error {
+	ctx, cancel := context.WithTimeout(context.Background(), 2*time.Second)
+	defer cancel()
+
+	res, err := c.ListRecurringJobs(ctx, &pb.EmptyMessage{})
+	if err != nil {
+		return errors.Wrap(err, "unable to list recurring jobs")
+	}
+
+	table := tablewriter.NewWriter(os.Stdout)
+	for _, recurringJob := range res.GetRecurringJobs() {
+		state := "active"
+		if recurringJob.GetPaused() {
+			state = "paused"
+		}
+		lastJob := recurringJob.GetLastJobId()
+		if lastJob == "" {
+			lastJob = "never scheduled"
+		}
+		table.Append([]string{
+			recurringJob.GetId(),
+			recurringJob.GetCronExpression(),
+			state,
+			lastJob,
+		})
+	}
+	table.Render()
+	return nil
+}
+
+func (c *conn) pauseRecurringJob(id string) error {
+	ctx, cancel := context.WithTimeout(context.Background(), 1*time.Second)
+	defer cancel()
+
+	if _, err := c.PauseRecurringJob(ctx, &pb.RecurringJobRequest{RecurringJobId: id}); err != nil {
+		return errors.Wrap(err, "unable to pause recurring job")
+	}
+
+	fmt.Printf("Recurring job %s successfully paused\n", id)
+	return nil
+}
+
+func (c *conn) resumeRecurringJob(id string) error {
+	ctx, cancel := context.WithTimeout(context.Background(), 1*time.Second)
+	defer cancel()
+
+	if _, err := c.ResumeRecurringJob(ctx, &pb.RecurringJobRequest{RecurringJobId: id}); err != nil {
+		return errors.Wrap(err, "unable to resume recurring job")
+	}
+
+	fmt.Printf("Recurring job %s successfully resumed\n", id)
+	return nil
+}
+
+func (c *conn) deleteRecurringJob(id string) error {
+	ctx, cancel := context.WithTimeout(context.Background(), 1*time.Second)
+	defer cancel()
+
+	if _, err := c.DeleteRecurringJob(ctx, &pb.RecurringJobRequest{RecurringJobId: id}); err != nil {
+		return errors.Wrap(err, "unable to delete recurring job")
+	}
+
+	fmt.Printf("Recurring job %s successfully deleted\n", id)
+	return nil
+}
+
 func (c *conn) describe(req *pb.DescribeRequest) (*pb.Description, error) {
 	ctx, cancelCtx := context.WithTimeout(context.Background(), 2*time.Second)
 	defer cancelCtx()
@@ -121,6 +201,7 @@ func (c *conn) describeJobs(jobID string) error {
 			table.Append([]string{
 				job.GetId(),
 				job.GetStatus().String(),
+				sampleDescription(job),
 				fmt.Sprint(age, "ago"),
 			})
 		}
@@ -130,6 +211,21 @@ func (c *conn) describeJobs(jobID string) error {
 	return nil
 }
 
+// sampleDescription describes how much of the input a job runs over.
+func sampleDescription(job *dpb.Job) string {
+	parts := []string{}
+	if fraction := job.GetSampleFraction(); fraction != 0 && fraction != 1 {
+		parts = append(parts, fmt.Sprintf("%g%% sample (seed %d)", fraction*100, job.GetSampleSeed()))
+	}
+	if limit := job.GetSampleRecordLimit(); limit != 0 {
+		parts = append(parts, fmt.Sprintf("first %d records", limit))
+	}
+	if len(parts) == 0 {
+		return "full input"
+	}
+	return strings.Join(parts, ", ")
+}
+
 func (c *conn) describeTasks(taskID, jobID string) error {
 	req := &pb.DescribeRequest{
 		Resource: pb.ResourceType_TASK,
@@ -157,6 +253,9 @@ func (c *conn) describeTasks(taskID, jobID string) error {
 			table.Append([]string{
 				task.GetId(),
 				task.GetStatus().String(),
+				fmt.Sprint("attempt ", task.GetAttempt()),
+				task.GetProgress().GetPhase(),
+				fmt.Sprint(task.GetProgress().GetRecordsProcessed(), " records"),
 				fmt.Sprint(age, "ago"),
 			})
 		}
@@ -0,0 +1,378 @@
+//! Generic `Reduce` implementations for common aggregations.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::Add;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use emitter::EmitFinal;
+use errors::*;
+use reducer::{Reduce, ReduceInputKV};
+
+/// `Sum` emits the sum of all values for a key.
+pub struct Sum<V> {
+    phantom: PhantomData<V>,
+}
+
+impl<V> Default for Sum<V> {
+    fn default() -> Self {
+        Sum {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<V> Sum<V> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<V> Reduce for Sum<V>
+where
+    V: Default + Serialize + DeserializeOwned + Add<Output = V>,
+{
+    type Value = V;
+    fn reduce<E>(&self, input: ReduceInputKV<Self::Value>, mut emitter: E) -> Result<()>
+    where
+        E: EmitFinal<Self::Value>,
+    {
+        let total = input.values.into_iter().fold(V::default(), |acc, x| acc + x);
+        emitter.emit(total).chain_err(|| "Error emitting sum.")
+    }
+}
+
+/// `Count` emits the number of values for a key.
+///
+/// `Reduce` emits values of the same type as the values it is given, so the count is emitted as a
+/// `V`. Values of mixed types, such as strings, can be counted with `Count<serde_json::Value>`.
+pub struct Count<V> {
+    phantom: PhantomData<V>,
+}
+
+impl<V> Default for Count<V> {
+    fn default() -> Self {
+        Count {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<V> Count<V> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<V> Reduce for Count<V>
+where
+    V: Default + Serialize + DeserializeOwned + From<u64>,
+{
+    type Value = V;
+    fn reduce<E>(&self, input: ReduceInputKV<Self::Value>, mut emitter: E) -> Result<()>
+    where
+        E: EmitFinal<Self::Value>,
+    {
+        emitter
+            .emit(V::from(input.values.len() as u64))
+            .chain_err(|| "Error emitting count.")
+    }
+}
+
+/// `Min` emits the smallest value for a key. Nothing is emitted for a key with no values.
+pub struct Min<V> {
+    phantom: PhantomData<V>,
+}
+
+impl<V> Default for Min<V> {
+    fn default() -> Self {
+        Min {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<V> Min<V> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<V> Reduce for Min<V>
+where
+    V: Default + Serialize + DeserializeOwned + PartialOrd,
+{
+    type Value = V;
+    fn reduce<E>(&self, input: ReduceInputKV<Self::Value>, mut emitter: E) -> Result<()>
+    where
+        E: EmitFinal<Self::Value>,
+    {
+        let mut values = input.values.into_iter();
+        if let Some(first) = values.next() {
+            let min = values.fold(first, |min, x| if x < min { x } else { min });
+            emitter.emit(min).chain_err(|| "Error emitting minimum.")?;
+        }
+        Ok(())
+    }
+}
+
+/// `Max` emits the largest value for a key. Nothing is emitted for a key with no values.
+pub struct Max<V> {
+    phantom: PhantomData<V>,
+}
+
+impl<V> Default for Max<V> {
+    fn default() -> Self {
+        Max {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<V> Max<V> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<V> Reduce for Max<V>
+where
+    V: Default + Serialize + DeserializeOwned + PartialOrd,
+{
+    type Value = V;
+    fn reduce<E>(&self, input: ReduceInputKV<Self::Value>, mut emitter: E) -> Result<()>
+    where
+        E: EmitFinal<Self::Value>,
+    {
+        let mut values = input.values.into_iter();
+        if let Some(first) = values.next() {
+            let max = values.fold(first, |max, x| if x > max { x } else { max });
+            emitter.emit(max).chain_err(|| "Error emitting maximum.")?;
+        }
+        Ok(())
+    }
+}
+
+/// `Distinct` emits each distinct value for a key once, in the order they were first seen.
+pub struct Distinct<V> {
+    phantom: PhantomData<V>,
+}
+
+impl<V> Default for Distinct<V> {
+    fn default() -> Self {
+        Distinct {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<V> Distinct<V> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<V> Reduce for Distinct<V>
+where
+    V: Default + Serialize + DeserializeOwned + Eq + Hash + Clone,
+{
+    type Value = V;
+    fn reduce<E>(&self, input: ReduceInputKV<Self::Value>, mut emitter: E) -> Result<()>
+    where
+        E: EmitFinal<Self::Value>,
+    {
+        let mut seen = HashSet::new();
+        for value in input.values {
+            if seen.insert(value.clone()) {
+                emitter
+                    .emit(value)
+                    .chain_err(|| "Error emitting distinct value.")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `TopK` emits the `k` largest values for a key, largest first.
+pub struct TopK<V> {
+    k: usize,
+    phantom: PhantomData<V>,
+}
+
+impl<V> TopK<V> {
+    pub fn new(k: usize) -> Self {
+        TopK {
+            k,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<V> Reduce for TopK<V>
+where
+    V: Default + Serialize + DeserializeOwned + PartialOrd,
+{
+    type Value = V;
+    fn reduce<E>(&self, input: ReduceInputKV<Self::Value>, mut emitter: E) -> Result<()>
+    where
+        E: EmitFinal<Self::Value>,
+    {
+        let mut values = input.values;
+        values.sort_by(|a, b| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+        for value in values.into_iter().take(self.k) {
+            emitter
+                .emit(value)
+                .chain_err(|| "Error emitting top value.")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use emitter::FinalVecEmitter;
+
+    fn run_reducer<R: Reduce>(reducer: &R, values: Vec<R::Value>) -> Vec<R::Value> {
+        let mut sink = Vec::new();
+        reducer
+            .reduce(
+                ReduceInputKV::new("key".to_owned(), values),
+                FinalVecEmitter::new(&mut sink),
+            )
+            .unwrap();
+        sink
+    }
+
+    /// Runs a reducer over the values of a key, given as the JSON the payload reads them from.
+    fn run_reducer_on_json<R: Reduce>(reducer: &R, values: &str) -> Vec<R::Value> {
+        let input = format!(r#"{{"key":"key","values":{}}}"#, values);
+        let input_kv: ReduceInputKV<R::Value> = serde_json::from_str(&input).unwrap();
+        let mut sink = Vec::new();
+        reducer
+            .reduce(input_kv, FinalVecEmitter::new(&mut sink))
+            .unwrap();
+        sink
+    }
+
+    #[test]
+    fn sum_numeric_values() {
+        assert_eq!(vec![6], run_reducer(&Sum::<u64>::new(), vec![1, 2, 3]));
+        assert_eq!(vec![1.5], run_reducer(&Sum::<f64>::new(), vec![1.0, 0.5]));
+    }
+
+    #[test]
+    fn count_values() {
+        assert_eq!(vec![3], run_reducer(&Count::<u64>::new(), vec![7, 7, 7]));
+        assert_eq!(vec![0], run_reducer(&Count::<u64>::new(), vec![]));
+    }
+
+    #[test]
+    fn count_string_values() {
+        let values = vec![Value::from("foo"), Value::from("bar"), Value::from("foo")];
+
+        assert_eq!(
+            vec![Value::from(3)],
+            run_reducer(&Count::<Value>::new(), values)
+        );
+    }
+
+    #[test]
+    fn min_max_numeric_values() {
+        assert_eq!(vec![-4], run_reducer(&Min::<i64>::new(), vec![3, -4, 10]));
+        assert_eq!(vec![10], run_reducer(&Max::<i64>::new(), vec![3, -4, 10]));
+    }
+
+    #[test]
+    fn min_max_string_values() {
+        let values = || vec!["pear".to_owned(), "apple".to_owned(), "zucchini".to_owned()];
+
+        assert_eq!(vec!["apple"], run_reducer(&Min::<String>::new(), values()));
+        assert_eq!(vec!["zucchini"], run_reducer(&Max::<String>::new(), values()));
+    }
+
+    #[test]
+    fn min_max_no_values() {
+        assert!(run_reducer(&Min::<u64>::new(), vec![]).is_empty());
+        assert!(run_reducer(&Max::<u64>::new(), vec![]).is_empty());
+    }
+
+    #[test]
+    fn distinct_values() {
+        assert_eq!(
+            vec![3, 1, 2],
+            run_reducer(&Distinct::<u64>::new(), vec![3, 1, 3, 2, 1])
+        );
+        assert_eq!(
+            vec!["foo", "bar"],
+            run_reducer(
+                &Distinct::<String>::new(),
+                vec!["foo".to_owned(), "bar".to_owned(), "foo".to_owned()]
+            )
+        );
+    }
+
+    #[test]
+    fn top_k_values() {
+        assert_eq!(
+            vec![9, 5],
+            run_reducer(&TopK::<u64>::new(2), vec![1, 9, 3, 5])
+        );
+        assert_eq!(
+            vec!["c", "b", "a"],
+            run_reducer(
+                &TopK::<String>::new(5),
+                vec!["b".to_owned(), "c".to_owned(), "a".to_owned()]
+            )
+        );
+    }
+
+    #[test]
+    fn min_max_strings_compare_lexicographically() {
+        let values = r#"["9", "10", "Banana", "apple", ""]"#;
+
+        assert_eq!(vec![""], run_reducer_on_json(&Min::<String>::new(), values));
+        assert_eq!(vec!["apple"], run_reducer_on_json(&Max::<String>::new(), values));
+        assert_eq!(
+            vec!["10"],
+            run_reducer_on_json(&Min::<String>::new(), r#"["9", "10"]"#)
+        );
+    }
+
+    #[test]
+    fn distinct_strings_are_case_sensitive() {
+        assert_eq!(
+            vec!["Foo", "foo", "foo "],
+            run_reducer_on_json(
+                &Distinct::<String>::new(),
+                r#"["Foo", "foo", "Foo", "foo ", "foo"]"#
+            )
+        );
+    }
+
+    #[test]
+    fn top_k_strings_keep_duplicates() {
+        assert_eq!(
+            vec!["pear", "pear", "apple"],
+            run_reducer_on_json(
+                &TopK::<String>::new(3),
+                r#"["apple", "pear", "Zebra", "pear", "apple"]"#
+            )
+        );
+        assert!(run_reducer_on_json(&TopK::<String>::new(0), r#"["apple"]"#).is_empty());
+    }
+
+    #[test]
+    fn count_json_values_of_any_type() {
+        assert_eq!(
+            vec![Value::from(4)],
+            run_reducer_on_json(&Count::<Value>::new(), r#"["foo", 1, null, {"bar": 2}]"#)
+        );
+    }
+}
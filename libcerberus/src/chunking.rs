@@ -0,0 +1,105 @@
+//! Module for cutting line based input into the chunks given to map tasks.
+//!
+//! The manager's `LineSplitter` and the `dry-run` subcommand both use `split_lines`, so a dry run
+//! gives the `Map` the same inputs it would get on the cluster.
+
+use std::io;
+use std::io::BufRead;
+
+/// `LineChunks` is an input cut into chunks by `split_lines`.
+#[derive(Debug, Default, PartialEq)]
+pub struct LineChunks {
+    /// The first line of the input without its line ending, if it was kept out of the chunks as a
+    /// header. Empty otherwise.
+    pub header: String,
+    /// The start and end of each chunk, as byte offsets from the start of the input. The end is
+    /// exclusive.
+    pub ranges: Vec<(u64, u64)>,
+}
+
+/// `split_lines` cuts an input into chunks of at most `chunk_size` bytes, breaking only at line
+/// endings. A line longer than `chunk_size` gets a chunk of its own, as there is nowhere else to
+/// put it. There is always at least one chunk, which is empty for an empty input.
+///
+/// If `has_header` is set, the first line is kept out of the chunks and returned as the header.
+pub fn split_lines<R: BufRead>(
+    mut reader: R,
+    chunk_size: u64,
+    has_header: bool,
+) -> io::Result<LineChunks> {
+    let mut ret = LineChunks::default();
+    let mut line = Vec::new();
+    let mut chunk_start: u64 = 0;
+    let mut chunk_len: u64 = 0;
+
+    if has_header {
+        let len = reader.read_until(b'\n', &mut line)?;
+        chunk_start = len as u64;
+        while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        ret.header = String::from_utf8(line.clone())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    }
+
+    loop {
+        line.clear();
+        let len = reader.read_until(b'\n', &mut line)? as u64;
+        if len == 0 {
+            break;
+        }
+        if chunk_len > 0 && chunk_len + len > chunk_size {
+            ret.ranges.push((chunk_start, chunk_start + chunk_len));
+            chunk_start += chunk_len;
+            chunk_len = 0;
+        }
+        chunk_len += len;
+    }
+    if chunk_len > 0 || ret.ranges.is_empty() {
+        ret.ranges.push((chunk_start, chunk_start + chunk_len));
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_lines_at_line_endings() {
+        let chunks = split_lines("foo\nbar\nbaz\n".as_bytes(), 8, false).unwrap();
+
+        assert_eq!(vec![(0, 8), (8, 12)], chunks.ranges);
+        assert_eq!("", chunks.header);
+    }
+
+    #[test]
+    fn split_lines_with_header() {
+        let chunks = split_lines("name,age\r\nalice,30\r\n".as_bytes(), 1024, true).unwrap();
+
+        assert_eq!("name,age", chunks.header);
+        assert_eq!(vec![(10, 20)], chunks.ranges);
+    }
+
+    #[test]
+    fn split_lines_long_line() {
+        let chunks = split_lines("foobarbaz\nqux\n".as_bytes(), 4, false).unwrap();
+
+        assert_eq!(vec![(0, 10), (10, 14)], chunks.ranges);
+    }
+
+    #[test]
+    fn split_lines_without_final_line_ending() {
+        let chunks = split_lines("foo\nbar".as_bytes(), 1024, false).unwrap();
+
+        assert_eq!(vec![(0, 7)], chunks.ranges);
+    }
+
+    #[test]
+    fn split_lines_empty_input() {
+        let chunks = split_lines("".as_bytes(), 1024, true).unwrap();
+
+        assert_eq!(vec![(0, 0)], chunks.ranges);
+        assert_eq!("", chunks.header);
+    }
+}
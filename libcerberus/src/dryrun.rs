@@ -0,0 +1,265 @@
+//! Module for running a payload's `Map` locally over real input files.
+//!
+//! The input is cut into chunks with `chunking::split_lines`, as the manager's `LineSplitter` does,
+//! so the mapper sees the same inputs it would on the cluster. The output is summarised per partition instead of
+//! being written out in full.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+use serde::Serialize;
+use serde_json;
+
+use chunking::split_lines;
+use emitter::IntermediateVecEmitter;
+use errors::*;
+use mapper::{Map, MapInputKV};
+use partition::HashPartitioner;
+use records::{split_records, InputDataKind};
+
+/// Default size of the input chunks, matching the manager's default `input_chunk_size`.
+pub const DEFAULT_CHUNK_SIZE: u64 = 67_108_864; // 64 MiB
+
+/// Number of sample pairs kept for each partition.
+pub const DEFAULT_SAMPLE_SIZE: usize = 5;
+
+/// `PartitionSummary` describes the map output that ended up in a single partition.
+#[derive(Debug, Default, PartialEq)]
+pub struct PartitionSummary {
+    pub pair_count: u64,
+    pub key_count: u64,
+    /// The first pairs emitted to the partition, encoded as JSON.
+    pub samples: Vec<(String, String)>,
+}
+
+/// `DryRunReport` is the result of running a `Map` over an input file with `dry_run`.
+#[derive(Debug, Default, PartialEq)]
+pub struct DryRunReport {
+    pub chunk_count: u64,
+    pub record_count: u64,
+    pub partitions: BTreeMap<u64, PartitionSummary>,
+}
+
+impl fmt::Display for DryRunReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} chunks, {} records, {} pairs",
+            self.chunk_count,
+            self.record_count,
+            self.partitions
+                .values()
+                .map(|summary| summary.pair_count)
+                .sum::<u64>()
+        )?;
+        for (partition, summary) in &self.partitions {
+            writeln!(
+                f,
+                "partition {}: {} pairs, {} distinct keys",
+                partition, summary.pair_count, summary.key_count
+            )?;
+            for (key, value) in &summary.samples {
+                writeln!(f, "    {} => {}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `split_chunks` cuts the contents of an input file into map inputs of at most `chunk_size`
+/// bytes, breaking only at line endings.
+///
+/// If `has_header` is set, the first line is kept out of the chunks and attached to each of them
+/// as their header.
+pub fn split_chunks(
+    path: &str,
+    data: &str,
+    chunk_size: u64,
+    has_header: bool,
+) -> Result<Vec<MapInputKV>> {
+    let chunks = split_lines(data.as_bytes(), chunk_size, has_header)
+        .chain_err(|| "Error splitting input into chunks.")?;
+    let header = chunks.header;
+    // Chunks end at line endings, so they can be sliced out of the string.
+    Ok(chunks
+        .ranges
+        .into_iter()
+        .map(|(start, end)| MapInputKV {
+            key: path.to_owned(),
+            value: data[start as usize..end as usize].to_owned(),
+            tag: String::new(),
+            header: header.clone(),
+        })
+        .collect())
+}
+
+/// Parses the number of partitions to split the output into. There must be at least one
+/// partition.
+pub fn parse_partition_count(value: &str) -> Result<u64> {
+    let partition_count = value
+        .parse::<u64>()
+        .chain_err(|| "Invalid partition count.")?;
+    if partition_count < 1 {
+        return Err("Partition count must be at least 1.".into());
+    }
+    Ok(partition_count)
+}
+
+/// `dry_run` runs the `Map` over every chunk of an input file, partitions its output and
+/// summarises each partition.
+///
+/// # Arguments
+///
+/// * `mapper` - The `Map` to run.
+/// * `path` - The path of the input file, given to the mapper as the input key.
+/// * `data` - The contents of the input file.
+/// * `partition_count` - The number of partitions to split the output into.
+/// * `input_kind` - The format of the input file.
+/// * `chunk_size` - The maximum size of each chunk in bytes.
+/// * `sample_size` - The number of sample pairs to keep for each partition.
+///
+/// Each chunk is mapped as a separate task, with `Map::finish` called at the end of each chunk.
+/// Unlike on the cluster, the same mapper is used for every chunk, so any state it keeps between
+/// records also carries over between chunks.
+pub fn dry_run<M>(
+    mapper: &M,
+    path: &str,
+    data: &str,
+    partition_count: u64,
+    input_kind: InputDataKind,
+    chunk_size: u64,
+    sample_size: usize,
+) -> Result<DryRunReport>
+where
+    M: Map,
+    M::Key: Hash,
+{
+    let has_header = input_kind == InputDataKind::Csv || input_kind == InputDataKind::Tsv;
+    let chunks = split_chunks(path, data, chunk_size, has_header)?;
+    let partitioner = HashPartitioner::new(partition_count);
+
+    let mut report = DryRunReport {
+        chunk_count: chunks.len() as u64,
+        ..Default::default()
+    };
+    let mut seen_keys: HashSet<(u64, String)> = HashSet::new();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let records = split_records(chunk, input_kind)
+            .chain_err(|| format!("Error splitting chunk {} into records.", index))?;
+        report.record_count += records.len() as u64;
+
+        let mut pairs: Vec<(M::Key, M::Value)> = Vec::new();
+        for record in records {
+            mapper
+                .map(record, IntermediateVecEmitter::new(&mut pairs))
+                .chain_err(|| format!("Error running map operation on chunk {}.", index))?;
+        }
+        mapper
+            .finish(IntermediateVecEmitter::new(&mut pairs))
+            .chain_err(|| format!("Error finishing map operation on chunk {}.", index))?;
+
+        for (key, value) in pairs {
+            let partition = partitioner.get_partition(&key);
+            let key_json = to_json(&key)?;
+            let summary = report
+                .partitions
+                .entry(partition)
+                .or_insert_with(Default::default);
+            summary.pair_count += 1;
+            if seen_keys.insert((partition, key_json.clone())) {
+                summary.key_count += 1;
+            }
+            if summary.samples.len() < sample_size {
+                summary.samples.push((key_json, to_json(&value)?));
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).chain_err(|| "Error encoding map output.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emitter::EmitIntermediate;
+
+    struct WordMapper;
+    impl Map for WordMapper {
+        type Key = String;
+        type Value = u64;
+        fn map<E>(&self, input: MapInputKV, mut emitter: E) -> Result<()>
+        where
+            E: EmitIntermediate<Self::Key, Self::Value>,
+        {
+            for word in input.value.split_whitespace() {
+                emitter.emit(word.to_owned(), 1)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn split_chunks_at_line_endings() {
+        let chunks = split_chunks("input", "foo\nbar\nbaz\n", 8, false).unwrap();
+
+        assert_eq!(2, chunks.len());
+        assert_eq!("foo\nbar\n", chunks[0].value);
+        assert_eq!("baz\n", chunks[1].value);
+        assert_eq!("input", chunks[1].key);
+    }
+
+    #[test]
+    fn split_chunks_with_header() {
+        let chunks = split_chunks("input", "name,age\nalice,30\n", 1024, true).unwrap();
+
+        assert_eq!(1, chunks.len());
+        assert_eq!("name,age", chunks[0].header);
+        assert_eq!("alice,30\n", chunks[0].value);
+    }
+
+    #[test]
+    fn split_chunks_long_line() {
+        let chunks = split_chunks("input", "foobarbaz\nqux\n", 4, false).unwrap();
+
+        assert_eq!(2, chunks.len());
+        assert_eq!("foobarbaz\n", chunks[0].value);
+        assert_eq!("qux\n", chunks[1].value);
+    }
+
+    #[test]
+    fn parse_partition_count_rejects_zero() {
+        assert_eq!(4, parse_partition_count("4").unwrap());
+        assert!(parse_partition_count("0").is_err());
+        assert!(parse_partition_count("-1").is_err());
+    }
+
+    #[test]
+    fn dry_run_summarises_partitions() {
+        let report = dry_run(
+            &WordMapper,
+            "input",
+            "foo bar\nfoo\n",
+            1,
+            InputDataKind::TextNewlines,
+            DEFAULT_CHUNK_SIZE,
+            2,
+        ).unwrap();
+
+        assert_eq!(1, report.chunk_count);
+        assert_eq!(1, report.record_count);
+        let summary = &report.partitions[&0];
+        assert_eq!(3, summary.pair_count);
+        assert_eq!(2, summary.key_count);
+        assert_eq!(
+            vec![
+                ("\"foo\"".to_owned(), "1".to_owned()),
+                ("\"bar\"".to_owned(), "1".to_owned()),
+            ],
+            summary.samples
+        );
+    }
+}
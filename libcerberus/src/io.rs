@@ -1,11 +1,55 @@
+use csv;
 use errors::*;
+use manifest::{ChecksumWriter, OutputManifest};
 use mapper::MapInputKV;
 use reducer::ReduceInputKV;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 use serde_json;
-use serialise::{FinalOutputObject, IntermediateOutputObject};
+use serde_json::Value;
+use serialise::{FinalOutputObject, IntermediateOutputObject, ProtocolHeader};
 use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// `OutputDataKind` is the format the final output of a reduce operation is written in.
+///
+/// It mirrors the `OutputDataKind` enum of the heracles datatypes, and can be parsed from the names
+/// used there.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum OutputDataKind {
+    /// A JSON array of `FinalOutputObject`s. This is the default.
+    #[serde(rename = "OUTPUT_JSON")]
+    Json,
+    /// One `key\tvalue` line per value.
+    #[serde(rename = "OUTPUT_TEXT")]
+    Text,
+    /// One `{"key":...,"value":...}` JSON object per line.
+    #[serde(rename = "OUTPUT_JSON_LINES")]
+    JsonLines,
+    /// CSV with a `key,value` header and one row per value.
+    #[serde(rename = "OUTPUT_CSV")]
+    Csv,
+}
+
+impl FromStr for OutputDataKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "OUTPUT_JSON" => Ok(OutputDataKind::Json),
+            "OUTPUT_TEXT" => Ok(OutputDataKind::Text),
+            "OUTPUT_JSON_LINES" => Ok(OutputDataKind::JsonLines),
+            "OUTPUT_CSV" => Ok(OutputDataKind::Csv),
+            _ => Err(format!("Unknown output data kind {:?}.", s).into()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct KeyValueRef<'a, V: 'a> {
+    key: &'a str,
+    value: &'a V,
+}
 
 /// `read_map_input` reads bytes from a source and returns a `MapInputKV`.
 ///
@@ -63,6 +107,116 @@ where
     Ok(())
 }
 
+/// `write_reduce_output_as` attempts to write a set of `FinalOutputObject`s to a given sink in the
+/// given format.
+///
+/// It returns an `OutputManifest` describing what was written, which can be used to verify the
+/// output later.
+pub fn write_reduce_output_as<W, V>(
+    sink: &mut W,
+    output: &[FinalOutputObject<V>],
+    kind: OutputDataKind,
+) -> Result<OutputManifest>
+where
+    W: Write,
+    V: Default + Serialize,
+{
+    let mut writer = ChecksumWriter::new(sink);
+    match kind {
+        OutputDataKind::Json => write_reduce_output(&mut writer, output),
+        OutputDataKind::Text => write_reduce_output_text(&mut writer, output),
+        OutputDataKind::JsonLines => write_reduce_output_json_lines(&mut writer, output),
+        OutputDataKind::Csv => write_reduce_output_csv(&mut writer, output),
+    }?;
+    let record_count = output
+        .iter()
+        .map(|object| object.values.len() as u64)
+        .sum();
+    Ok(writer.manifest(kind, record_count))
+}
+
+/// `write_reduce_output_text` writes each value of a set of `FinalOutputObject`s as a
+/// `key\tvalue` line.
+///
+/// String values are written as they are, other values are written as JSON.
+pub fn write_reduce_output_text<W, V>(sink: &mut W, output: &[FinalOutputObject<V>]) -> Result<()>
+where
+    W: Write,
+    V: Default + Serialize,
+{
+    for object in output {
+        for value in &object.values {
+            writeln!(sink, "{}\t{}", object.key, value_to_string(value)?)
+                .chain_err(|| "Error writing to sink.")?;
+        }
+    }
+    Ok(())
+}
+
+/// `write_reduce_output_json_lines` writes each value of a set of `FinalOutputObject`s as a
+/// `{"key":...,"value":...}` JSON object on its own line.
+pub fn write_reduce_output_json_lines<W, V>(
+    sink: &mut W,
+    output: &[FinalOutputObject<V>],
+) -> Result<()>
+where
+    W: Write,
+    V: Default + Serialize,
+{
+    for object in output {
+        for value in &object.values {
+            let record = KeyValueRef {
+                key: &object.key,
+                value,
+            };
+            serde_json::to_writer(&mut *sink, &record).chain_err(|| "Error writing to sink.")?;
+            sink.write_all(b"\n").chain_err(|| "Error writing to sink.")?;
+        }
+    }
+    Ok(())
+}
+
+/// `write_reduce_output_csv` writes each value of a set of `FinalOutputObject`s as a `key,value`
+/// CSV row, after a header row.
+///
+/// String values are written as they are, other values are written as JSON.
+pub fn write_reduce_output_csv<W, V>(sink: &mut W, output: &[FinalOutputObject<V>]) -> Result<()>
+where
+    W: Write,
+    V: Default + Serialize,
+{
+    let mut writer = csv::Writer::from_writer(sink);
+    writer
+        .write_record(&["key", "value"])
+        .chain_err(|| "Error writing to sink.")?;
+    for object in output {
+        for value in &object.values {
+            writer
+                .write_record(&[object.key.as_str(), value_to_string(value)?.as_str()])
+                .chain_err(|| "Error writing to sink.")?;
+        }
+    }
+    writer.flush().chain_err(|| "Error writing to sink.")?;
+    Ok(())
+}
+
+/// Converts a value to a string for the plain text output formats.
+fn value_to_string<V: Serialize>(value: &V) -> Result<String> {
+    match serde_json::to_value(value).chain_err(|| "Error serialising output value.")? {
+        Value::String(s) => Ok(s),
+        other => Ok(other.to_string()),
+    }
+}
+
+/// `write_protocol_header` writes a `ProtocolHeader` line to a given sink. It is written before any
+/// streamed output records.
+pub fn write_protocol_header<W: Write>(sink: &mut W) -> Result<()> {
+    serde_json::to_writer(&mut *sink, &ProtocolHeader::default())
+        .chain_err(|| "Error writing to sink.")?;
+    sink.write_all(b"\n").chain_err(|| "Error writing to sink.")?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use serialise::IntermediateOutputPair;
@@ -77,6 +231,24 @@ mod tests {
         let expected_result = MapInputKV {
             key: "foo".to_owned(),
             value: "bar".to_owned(),
+            tag: String::new(),
+            header: String::new(),
+        };
+
+        let result: MapInputKV = read_map_input(&mut cursor).unwrap();
+
+        assert_eq!(expected_result, result);
+    }
+
+    #[test]
+    fn read_tagged_map_input_kv() {
+        let test_string = r#"{"key":"foo", "value":"bar", "tag":"users"}"#;
+        let mut cursor = Cursor::new(test_string);
+        let expected_result = MapInputKV {
+            key: "foo".to_owned(),
+            value: "bar".to_owned(),
+            tag: "users".to_owned(),
+            header: String::new(),
         };
 
         let result: MapInputKV = read_map_input(&mut cursor).unwrap();
@@ -133,11 +305,12 @@ mod tests {
             ],
         );
         let test_object = IntermediateOutputObject {
+            protocol_version: 1,
             partitions: partitions,
         };
 
         let expected_json_string = String::from(
-            r#"{"partitions":{"0":[{"key":"foo_intermediate","value":"bar"},
+            r#"{"protocol_version":1,"partitions":{"0":[{"key":"foo_intermediate","value":"bar"},
 {"key":"foo_intermediate","value":"baz"}]}}"#,
         ).replace('\n', "");
 
@@ -153,10 +326,12 @@ mod tests {
     #[test]
     fn write_final_output_object() {
         let test_object = vec![FinalOutputObject {
+            protocol_version: 1,
             key: "test".to_string(),
             values: vec!["barbaz", "bazbar"],
         }];
-        let expected_json_string = r#"[{"key":"test","values":["barbaz","bazbar"]}]"#;
+        let expected_json_string =
+            r#"[{"protocol_version":1,"key":"test","values":["barbaz","bazbar"]}]"#;
         let output_vector: Vec<u8> = Vec::new();
         let mut cursor = Cursor::new(output_vector);
 
@@ -165,4 +340,90 @@ mod tests {
         let output_string = String::from_utf8(cursor.into_inner()).unwrap();
         assert_eq!(expected_json_string, output_string);
     }
+
+    #[test]
+    fn write_protocol_header_line() {
+        let mut cursor = Cursor::new(Vec::new());
+
+        write_protocol_header(&mut cursor).unwrap();
+
+        let output_string = String::from_utf8(cursor.into_inner()).unwrap();
+        assert_eq!("{\"protocol_version\":2}\n", output_string);
+    }
+
+    fn test_output_objects() -> Vec<FinalOutputObject<Value>> {
+        vec![
+            FinalOutputObject {
+                protocol_version: 1,
+                key: "foo".to_string(),
+                values: vec![Value::from("bar"), Value::from("baz, qux")],
+            },
+            FinalOutputObject {
+                protocol_version: 1,
+                key: "count".to_string(),
+                values: vec![Value::from(42)],
+            },
+        ]
+    }
+
+    #[test]
+    fn write_final_output_text() {
+        let mut cursor = Cursor::new(Vec::new());
+
+        write_reduce_output_as(&mut cursor, &test_output_objects(), OutputDataKind::Text)
+            .unwrap();
+
+        let output_string = String::from_utf8(cursor.into_inner()).unwrap();
+        assert_eq!("foo\tbar\nfoo\tbaz, qux\ncount\t42\n", output_string);
+    }
+
+    #[test]
+    fn write_final_output_json_lines() {
+        let mut cursor = Cursor::new(Vec::new());
+        let expected_output = r#"{"key":"foo","value":"bar"}
+{"key":"foo","value":"baz, qux"}
+{"key":"count","value":42}
+"#;
+
+        write_reduce_output_as(
+            &mut cursor,
+            &test_output_objects(),
+            OutputDataKind::JsonLines,
+        ).unwrap();
+
+        let output_string = String::from_utf8(cursor.into_inner()).unwrap();
+        assert_eq!(expected_output, output_string);
+    }
+
+    #[test]
+    fn write_final_output_csv() {
+        let mut cursor = Cursor::new(Vec::new());
+
+        write_reduce_output_as(&mut cursor, &test_output_objects(), OutputDataKind::Csv).unwrap();
+
+        let output_string = String::from_utf8(cursor.into_inner()).unwrap();
+        assert_eq!(
+            "key,value\nfoo,bar\nfoo,\"baz, qux\"\ncount,42\n",
+            output_string
+        );
+    }
+
+    #[test]
+    fn write_final_output_manifest() {
+        let mut cursor = Cursor::new(Vec::new());
+
+        let manifest =
+            write_reduce_output_as(&mut cursor, &test_output_objects(), OutputDataKind::Csv)
+                .unwrap();
+
+        assert_eq!(OutputDataKind::Csv, manifest.output_kind);
+        assert_eq!(3, manifest.record_count);
+        assert_eq!(cursor.into_inner().len() as u64, manifest.byte_count);
+    }
+
+    #[test]
+    fn parse_output_data_kind() {
+        assert_eq!(OutputDataKind::Csv, "OUTPUT_CSV".parse().unwrap());
+        assert!("csv".parse::<OutputDataKind>().is_err());
+    }
 }
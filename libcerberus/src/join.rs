@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use emitter::EmitIntermediate;
+use errors::*;
+use mapper::MapInputKV;
+
+/// `TaggedValue` is an intermediate value labelled with the tag of the dataset it came from.
+///
+/// It is used for reduce-side joins, where a `Reduce` receives the values for a key from several
+/// datasets and needs to tell them apart.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TaggedValue<V> {
+    pub tag: String,
+    pub value: V,
+}
+
+impl<V> TaggedValue<V> {
+    pub fn new(tag: String, value: V) -> Self {
+        TaggedValue { tag, value }
+    }
+}
+
+/// A struct implementing `EmitIntermediate` which tags every emitted value with the tag of the
+/// current map input before passing it on to another emitter.
+pub struct TaggingEmitter<E> {
+    tag: String,
+    emitter: E,
+}
+
+impl<E> TaggingEmitter<E> {
+    /// Constructs a new `TaggingEmitter` which uses the tag of the given `MapInputKV`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The `MapInputKV` being processed by the `Map`.
+    /// * `emitter` - The emitter to receive the tagged key-value pairs.
+    pub fn new(input: &MapInputKV, emitter: E) -> Self {
+        TaggingEmitter {
+            tag: input.tag.clone(),
+            emitter,
+        }
+    }
+}
+
+impl<K, V, E> EmitIntermediate<K, V> for TaggingEmitter<E>
+where
+    K: Serialize,
+    V: Serialize,
+    E: EmitIntermediate<K, TaggedValue<V>>,
+{
+    fn emit(&mut self, key: K, value: V) -> Result<()> {
+        self.emitter
+            .emit(key, TaggedValue::new(self.tag.clone(), value))
+            .chain_err(|| "Error emitting tagged value.")
+    }
+}
+
+/// `group_by_tag` splits the values given to a `Reduce` into groups based on their tag.
+///
+/// The order of values within each group is preserved.
+pub fn group_by_tag<V>(values: Vec<TaggedValue<V>>) -> HashMap<String, Vec<V>> {
+    let mut groups: HashMap<String, Vec<V>> = HashMap::new();
+    for tagged in values {
+        groups
+            .entry(tagged.tag)
+            .or_insert_with(Default::default)
+            .push(tagged.value);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emitter::IntermediateVecEmitter;
+    use serde_json;
+
+    #[test]
+    fn tagging_emitter_uses_input_tag() {
+        let mut vec: Vec<(String, TaggedValue<u64>)> = Vec::new();
+        let input = MapInputKV::with_tag("foo".to_owned(), "bar".to_owned(), "users".to_owned());
+
+        {
+            let mut emitter = TaggingEmitter::new(&input, IntermediateVecEmitter::new(&mut vec));
+            emitter.emit("user_1".to_owned(), 42).unwrap();
+        }
+
+        assert_eq!("user_1", vec[0].0);
+        assert_eq!(TaggedValue::new("users".to_owned(), 42), vec[0].1);
+    }
+
+    #[test]
+    fn group_by_tag_groups_values() {
+        let values = vec![
+            TaggedValue::new("users".to_owned(), "alice"),
+            TaggedValue::new("events".to_owned(), "login"),
+            TaggedValue::new("events".to_owned(), "logout"),
+        ];
+
+        let groups = group_by_tag(values);
+
+        assert_eq!(2, groups.len());
+        assert_eq!(vec!["alice"], groups["users"]);
+        assert_eq!(vec!["login", "logout"], groups["events"]);
+    }
+
+    #[test]
+    fn tagged_value_json_format() {
+        let value = TaggedValue::new("users".to_owned(), "alice");
+        let expected_json_string = r#"{"tag":"users","value":"alice"}"#;
+
+        let json_string = serde_json::to_string(&value).unwrap();
+
+        assert_eq!(expected_json_string, json_string);
+    }
+}
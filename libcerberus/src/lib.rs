@@ -3,6 +3,7 @@
 extern crate bson;
 extern crate chrono;
 extern crate clap;
+extern crate csv;
 #[macro_use]
 extern crate error_chain;
 #[macro_use]
@@ -15,6 +16,10 @@ extern crate uuid;
 
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 
+/// Version of the JSON layout used between the worker and the payload. It must be bumped whenever
+/// the format of the input or output documents changes.
+pub const PROTOCOL_VERSION: u32 = 2;
+
 mod errors {
     error_chain!{
         foreign_links {
@@ -22,21 +27,48 @@ mod errors {
             Io(::std::io::Error);
             SerdeJson(::serde_json::error::Error);
         }
+
+        errors {
+            ProtocolVersionMismatch(worker_version: u32, payload_version: u32) {
+                description("worker and payload protocol versions do not match")
+                display(
+                    "Protocol version mismatch: the worker uses version {}, but this payload \
+                     supports version {}. Rebuild the payload against a matching libcerberus.",
+                    worker_version,
+                    payload_version
+                )
+            }
+            OutputVerificationFailed(reason: String) {
+                description("output file does not match its manifest")
+                display("Output file does not match its manifest: {}.", reason)
+            }
+        }
     }
 }
 
+pub mod aggregators;
+pub mod chunking;
+pub mod dryrun;
 pub mod emitter;
 pub mod io;
+pub mod join;
+pub mod manifest;
 pub mod mapper;
 pub mod partition;
+pub mod progress;
+pub mod records;
 pub mod reducer;
 pub mod runner;
 pub mod serialise;
 
 pub use errors::*;
 pub use emitter::{EmitFinal, EmitIntermediate, EmitPartitionedIntermediate};
+pub use io::OutputDataKind;
+pub use join::{group_by_tag, TaggedValue, TaggingEmitter};
+pub use manifest::{verify_output, OutputManifest};
 pub use mapper::{Map, MapInputKV};
 pub use partition::{HashPartitioner, Partition, PartitionInputPairs};
+pub use records::InputDataKind;
 pub use reducer::{Reduce, ReduceInputKV};
 pub use runner::*;
 pub use serialise::{FinalOutputObject, IntermediateOutputObject};
@@ -0,0 +1,244 @@
+//! Module for describing and verifying the final output files of a reduce operation.
+//!
+//! When asked to, the payload writes an `OutputManifest` holding a checksum, size and record count
+//! of its output. The worker saves the manifest next to the output file once the output is in
+//! place. Consumers of the output can use `verify_output` to check that an output file is complete
+//! before reading it.
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use csv;
+use serde_json;
+use serde_json::Value;
+
+use super::PROTOCOL_VERSION;
+use errors::*;
+use io::OutputDataKind;
+
+/// Extension appended to the path of an output file to get the path of its manifest.
+pub const MANIFEST_EXTENSION: &str = "manifest";
+
+/// `OutputManifest` describes the contents of a final output file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct OutputManifest {
+    pub protocol_version: u32,
+    pub output_kind: OutputDataKind,
+    /// CRC-32 (IEEE) checksum of the file contents, as 8 lowercase hex digits.
+    pub checksum: String,
+    pub byte_count: u64,
+    /// Number of values in the file.
+    pub record_count: u64,
+}
+
+/// A struct implementing `Write` which computes the checksum and size of everything written
+/// through it.
+pub struct ChecksumWriter<W> {
+    sink: W,
+    crc: u32,
+    byte_count: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(sink: W) -> Self {
+        ChecksumWriter {
+            sink,
+            crc: 0,
+            byte_count: 0,
+        }
+    }
+
+    /// Builds the manifest of the output written so far.
+    pub fn manifest(&self, output_kind: OutputDataKind, record_count: u64) -> OutputManifest {
+        OutputManifest {
+            protocol_version: PROTOCOL_VERSION,
+            output_kind,
+            checksum: format!("{:08x}", self.crc),
+            byte_count: self.byte_count,
+            record_count,
+        }
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.sink.write(buf)?;
+        self.crc = crc32(self.crc, &buf[..written]);
+        self.byte_count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+/// Continues a CRC-32 (IEEE) checksum over `bytes`. Start with a `crc` of 0.
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Returns the path of the manifest belonging to an output file.
+pub fn manifest_path<P: AsRef<Path>>(output_path: P) -> PathBuf {
+    let mut path = output_path.as_ref().as_os_str().to_owned();
+    path.push(".");
+    path.push(MANIFEST_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// `write_manifest` writes an `OutputManifest` as JSON to the file at `path`.
+pub fn write_manifest<P: AsRef<Path>>(path: P, manifest: &OutputManifest) -> Result<()> {
+    let mut file = File::create(path).chain_err(|| "Error creating manifest file.")?;
+    serde_json::to_writer(&mut file, manifest).chain_err(|| "Error writing manifest.")?;
+    Ok(())
+}
+
+/// `verify_output` checks an output file against the manifest next to it, and returns the
+/// manifest if the file matches it.
+///
+/// The checksum and size are always checked. The record count is checked for every output kind
+/// except `Text`, where a value spanning several lines can not be told apart from several values.
+pub fn verify_output<P: AsRef<Path>>(output_path: P) -> Result<OutputManifest> {
+    let output_path = output_path.as_ref();
+    let manifest_file =
+        File::open(manifest_path(output_path)).chain_err(|| "Error opening manifest file.")?;
+    let manifest: OutputManifest =
+        serde_json::from_reader(manifest_file).chain_err(|| "Error parsing manifest.")?;
+
+    let mut data = Vec::new();
+    File::open(output_path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .chain_err(|| "Error reading output file.")?;
+
+    if data.len() as u64 != manifest.byte_count {
+        return Err(ErrorKind::OutputVerificationFailed(format!(
+            "expected {} bytes, found {}",
+            manifest.byte_count,
+            data.len()
+        )).into());
+    }
+    let checksum = format!("{:08x}", crc32(0, &data));
+    if checksum != manifest.checksum {
+        return Err(ErrorKind::OutputVerificationFailed(format!(
+            "expected checksum {}, found {}",
+            manifest.checksum, checksum
+        )).into());
+    }
+    if let Some(record_count) = count_records(&data, manifest.output_kind)? {
+        if record_count != manifest.record_count {
+            return Err(ErrorKind::OutputVerificationFailed(format!(
+                "expected {} records, found {}",
+                manifest.record_count, record_count
+            )).into());
+        }
+    }
+    Ok(manifest)
+}
+
+/// Counts the values in an output file, if the output kind allows it.
+fn count_records(data: &[u8], output_kind: OutputDataKind) -> Result<Option<u64>> {
+    let count = match output_kind {
+        OutputDataKind::Json => {
+            let objects: Vec<Value> =
+                serde_json::from_slice(data).chain_err(|| "Error parsing output file.")?;
+            objects
+                .iter()
+                .map(|object| match object.get("values") {
+                    Some(Value::Array(values)) => values.len() as u64,
+                    _ => 0,
+                })
+                .sum()
+        }
+        OutputDataKind::JsonLines => data.split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .count() as u64,
+        OutputDataKind::Csv => csv::Reader::from_reader(data).records().count() as u64,
+        OutputDataKind::Text => return Ok(None),
+    };
+    Ok(Some(count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn write_output(data: &[u8], output_kind: OutputDataKind, record_count: u64) -> PathBuf {
+        let path = env::temp_dir().join(format!("output_{}", Uuid::new_v4()));
+        let mut writer = ChecksumWriter::new(File::create(&path).unwrap());
+        writer.write_all(data).unwrap();
+        write_manifest(
+            manifest_path(&path),
+            &writer.manifest(output_kind, record_count),
+        ).unwrap();
+        path
+    }
+
+    fn remove_output(path: &Path) {
+        fs::remove_file(path).unwrap();
+        fs::remove_file(manifest_path(path)).unwrap();
+    }
+
+    #[test]
+    fn crc32_known_value() {
+        assert_eq!(0xcbf4_3926, crc32(0, b"123456789"));
+        assert_eq!(0xcbf4_3926, crc32(crc32(0, b"1234"), b"56789"));
+    }
+
+    #[test]
+    fn manifest_path_appends_extension() {
+        assert_eq!(
+            PathBuf::from("/tmp/output.json.manifest"),
+            manifest_path("/tmp/output.json")
+        );
+    }
+
+    #[test]
+    fn verify_complete_output() {
+        let data = br#"[{"protocol_version":1,"key":"foo","values":["bar","baz"]}]"#;
+        let path = write_output(data, OutputDataKind::Json, 2);
+
+        let result = verify_output(&path);
+        remove_output(&path);
+
+        let manifest = result.unwrap();
+        assert_eq!(2, manifest.record_count);
+        assert_eq!(data.len() as u64, manifest.byte_count);
+    }
+
+    #[test]
+    fn verify_truncated_output() {
+        let path = write_output(b"foo\tbar\nfoo\tbaz\n", OutputDataKind::Text, 2);
+        fs::write(&path, b"foo\tbar\n").unwrap();
+
+        let result = verify_output(&path);
+        remove_output(&path);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn verify_wrong_record_count() {
+        let path = write_output(b"key,value\nfoo,bar\n", OutputDataKind::Csv, 2);
+
+        let result = verify_output(&path);
+        remove_output(&path);
+
+        assert!(result.is_err());
+    }
+}
@@ -1,20 +1,53 @@
 use emitter::EmitIntermediate;
 use errors::*;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
 
 /// The `MapInputKV` is a struct for passing input data to a `Map`.
 ///
 /// `MapInputKV` is a thin wrapper around a `(String, String)`, used for creating a clearer API.
 /// It can be constructed normally or using `MapInputKV::new()`.
+///
+/// When a job reads from several tagged input directories, `tag` holds the tag of the directory
+/// the input came from. It is empty for untagged input.
+///
+/// For record formats with a header line (CSV and TSV), `header` holds the header line of the
+/// input file.
 #[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
 pub struct MapInputKV {
     pub key: String,
     pub value: String,
+    #[serde(default)]
+    pub tag: String,
+    #[serde(default)]
+    pub header: String,
 }
 
 impl MapInputKV {
     pub fn new(key: String, value: String) -> Self {
-        MapInputKV { key, value }
+        MapInputKV {
+            key,
+            value,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_tag(key: String, value: String, tag: String) -> Self {
+        MapInputKV {
+            key,
+            value,
+            tag,
+            ..Default::default()
+        }
+    }
+
+    /// Parses the value as a typed record.
+    ///
+    /// This is intended for jobs using a record input format, where the value of each
+    /// `MapInputKV` is a single record encoded as JSON.
+    pub fn record<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_str(&self.value).chain_err(|| "Error parsing input record.")
     }
 }
 
@@ -31,18 +64,34 @@ impl MapInputKV {
 ///
 /// An empty result used for returning an error. Outputs of the map operation are sent out through
 /// the `emitter`.
+///
+/// # In-mapper combining
+///
+/// `finish` is called once after the last input record of a map task has been mapped. A mapper can
+/// aggregate values across records, using interior mutability such as a `RefCell`, and emit the
+/// aggregates from `finish` instead of emitting a pair for every record.
 pub trait Map {
     type Key: Default + Serialize;
     type Value: Default + Serialize;
     fn map<E>(&self, input: MapInputKV, emitter: E) -> Result<()>
     where
         E: EmitIntermediate<Self::Key, Self::Value>;
+
+    /// Called after the last input record has been mapped. Does nothing by default.
+    fn finish<E>(&self, _emitter: E) -> Result<()>
+    where
+        E: EmitIntermediate<Self::Key, Self::Value>,
+    {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use emitter::IntermediateVecEmitter;
+    use std::cell::RefCell;
+    use std::cmp;
 
     struct TestMapper;
     impl Map for TestMapper {
@@ -84,4 +133,56 @@ mod tests {
         assert_eq!("this is a", vec[0].0);
         assert_eq!("test", vec[0].1);
     }
+
+    struct MaxLengthMapper {
+        max_length: RefCell<u64>,
+    }
+    impl Map for MaxLengthMapper {
+        type Key = String;
+        type Value = u64;
+        fn map<E>(&self, input: MapInputKV, _emitter: E) -> Result<()>
+        where
+            E: EmitIntermediate<Self::Key, Self::Value>,
+        {
+            let mut max_length = self.max_length.borrow_mut();
+            *max_length = cmp::max(*max_length, input.value.len() as u64);
+            Ok(())
+        }
+
+        fn finish<E>(&self, mut emitter: E) -> Result<()>
+        where
+            E: EmitIntermediate<Self::Key, Self::Value>,
+        {
+            emitter.emit("max_length".to_owned(), *self.max_length.borrow())
+        }
+    }
+
+    #[test]
+    fn test_mapper_finish_emits_aggregate() {
+        let mut vec: Vec<(String, u64)> = Vec::new();
+        let mapper = MaxLengthMapper {
+            max_length: RefCell::new(0),
+        };
+
+        for value in &["foo", "foobar", "baz"] {
+            let input = MapInputKV::new("test_key".to_owned(), value.to_string());
+            mapper
+                .map(input, IntermediateVecEmitter::new(&mut vec))
+                .unwrap();
+        }
+        mapper.finish(IntermediateVecEmitter::new(&mut vec)).unwrap();
+
+        assert_eq!(vec![("max_length".to_owned(), 6)], vec);
+    }
+
+    #[test]
+    fn test_mapper_default_finish() {
+        let mut vec: Vec<(String, String)> = Vec::new();
+
+        TestMapper
+            .finish(IntermediateVecEmitter::new(&mut vec))
+            .unwrap();
+
+        assert!(vec.is_empty());
+    }
 }
@@ -3,7 +3,7 @@ use std::hash::{Hash, Hasher};
 
 use serde::Serialize;
 
-use emitter::EmitPartitionedIntermediate;
+use emitter::{EmitIntermediate, EmitPartitionedIntermediate};
 use errors::*;
 
 /// The `PartitionInputPairs` is a struct for passing input data to a `Partition`.
@@ -60,6 +60,11 @@ impl HashPartitioner {
         HashPartitioner { partition_count }
     }
 
+    /// Returns the partition a given key belongs to.
+    pub fn get_partition<K: Hash>(&self, key: &K) -> u64 {
+        self.calculate_hash(key) % self.partition_count
+    }
+
     fn calculate_hash<T: Hash>(&self, t: &T) -> u64 {
         let mut hasher = DefaultHasher::new();
         t.hash(&mut hasher);
@@ -77,8 +82,7 @@ where
         E: EmitPartitionedIntermediate<K, V>,
     {
         for (key, value) in input.pairs {
-            let hash: u64 = self.calculate_hash(&key);
-            let partition = hash % self.partition_count;
+            let partition = self.get_partition(&key);
             emitter
                 .emit(partition, key, value)
                 .chain_err(|| "Error partitioning map output.")?;
@@ -86,3 +90,73 @@ where
         Ok(())
     }
 }
+
+/// A struct implementing `EmitIntermediate` which partitions each key-value pair as soon as it is
+/// emitted, using a `HashPartitioner`, and passes it on to an `EmitPartitionedIntermediate`.
+///
+/// This allows the output of a `Map` to be partitioned without first collecting it in memory.
+pub struct HashPartitioningEmitter<'a, E> {
+    partitioner: &'a HashPartitioner,
+    emitter: E,
+}
+
+impl<'a, E> HashPartitioningEmitter<'a, E> {
+    /// Constructs a new `HashPartitioningEmitter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `partitioner` - The `HashPartitioner` used to decide the partition of each key.
+    /// * `emitter` - The emitter to receive the partitioned key-value pairs.
+    pub fn new(partitioner: &'a HashPartitioner, emitter: E) -> Self {
+        HashPartitioningEmitter {
+            partitioner,
+            emitter,
+        }
+    }
+}
+
+impl<'a, K, V, E> EmitIntermediate<K, V> for HashPartitioningEmitter<'a, E>
+where
+    K: Default + Serialize + Hash,
+    V: Default + Serialize,
+    E: EmitPartitionedIntermediate<K, V>,
+{
+    fn emit(&mut self, key: K, value: V) -> Result<()> {
+        let partition = self.partitioner.get_partition(&key);
+        self.emitter
+            .emit(partition, key, value)
+            .chain_err(|| "Error partitioning map output.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialise::{IntermediateOutputObject, IntermediateOutputObjectEmitter};
+
+    #[test]
+    fn hash_partitioning_emitter_matches_partitioner() {
+        let partitioner = HashPartitioner::new(4);
+        let pairs = vec![("foo", 1), ("bar", 2), ("baz", 3), ("foo", 4)];
+        let mut expected_output = IntermediateOutputObject::default();
+        let mut output = IntermediateOutputObject::default();
+
+        partitioner
+            .partition(
+                PartitionInputPairs::new(pairs.clone()),
+                IntermediateOutputObjectEmitter::new(&mut expected_output),
+            )
+            .unwrap();
+        {
+            let mut emitter = HashPartitioningEmitter::new(
+                &partitioner,
+                IntermediateOutputObjectEmitter::new(&mut output),
+            );
+            for (key, value) in pairs {
+                emitter.emit(key, value).unwrap();
+            }
+        }
+
+        assert_eq!(expected_output, output);
+    }
+}
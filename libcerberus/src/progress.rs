@@ -0,0 +1,391 @@
+//! Module for reporting the progress of a running payload to the worker.
+//!
+//! Progress is written periodically as a JSON object to a side channel file, which the worker can
+//! read while the payload is running. The file is replaced atomically on every write, so readers
+//! never see a partially written report.
+//!
+//! The report also carries `Metrics` about where the payload spent its time, which are complete
+//! once the payload has finished.
+
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use serde::Serialize;
+use serde_json;
+
+use emitter::{EmitFinal, EmitIntermediate};
+use errors::*;
+
+/// How often the progress file is rewritten while a payload is running.
+pub const DEFAULT_REPORT_INTERVAL_SECS: u64 = 5;
+
+/// `Phase` is the current stage of work of the payload.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Starting,
+    ReadingInput,
+    Map,
+    Partition,
+    Reduce,
+    WritingOutput,
+    Done,
+}
+
+/// `Progress` is a snapshot of the work done by the payload so far.
+///
+/// During a map operation `records_processed` counts the intermediate pairs emitted, and during a
+/// reduce operation it counts the keys reduced. `time_updated` is the time the report was written.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Progress {
+    pub phase: Phase,
+    pub records_processed: u64,
+    pub bytes_consumed: u64,
+    pub time_updated: i64,
+    #[serde(default)]
+    pub metrics: Metrics,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress {
+            phase: Phase::Starting,
+            records_processed: 0,
+            bytes_consumed: 0,
+            time_updated: Utc::now().timestamp(),
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+/// `Metrics` records how long the payload spent in each phase, and how many records went through
+/// it.
+///
+/// Time spent reading the input includes deserialising it, and time spent writing the output
+/// includes serialising it.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Metrics {
+    pub read_input_ms: u64,
+    pub map_ms: u64,
+    pub partition_ms: u64,
+    pub reduce_ms: u64,
+    pub write_output_ms: u64,
+    /// Number of records given to the map, or keys given to the reduce.
+    pub input_records: u64,
+    /// Number of pairs emitted by the map, or values emitted by the reduce.
+    pub output_records: u64,
+    /// Largest number of pairs emitted by the map for a single input record, or largest number of
+    /// values given to the reduce for a single key.
+    pub peak_records: u64,
+}
+
+impl Metrics {
+    fn add_phase_time(&mut self, phase: Phase, elapsed: Duration) {
+        let ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_nanos()) / 1_000_000;
+        match phase {
+            Phase::ReadingInput => self.read_input_ms += ms,
+            Phase::Map => self.map_ms += ms,
+            Phase::Partition => self.partition_ms += ms,
+            Phase::Reduce => self.reduce_ms += ms,
+            Phase::WritingOutput => self.write_output_ms += ms,
+            Phase::Starting | Phase::Done => {}
+        }
+    }
+}
+
+/// `ProgressReporter` tracks the `Progress` of the payload and writes it to a file from a
+/// background thread.
+///
+/// A reporter created with `ProgressReporter::disabled` tracks progress but never writes it.
+pub struct ProgressReporter {
+    progress: Arc<Mutex<Progress>>,
+    phase_started: Mutex<Instant>,
+    path: Option<PathBuf>,
+    stop_sender: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl ProgressReporter {
+    /// Constructs a new `ProgressReporter` which writes to the file at `path` every `interval`.
+    pub fn new<P: AsRef<Path>>(path: P, interval: Duration) -> Self {
+        let progress = Arc::new(Mutex::new(Progress::default()));
+        let path = path.as_ref().to_path_buf();
+        let (stop_sender, stop_receiver) = mpsc::channel();
+
+        let thread_progress = Arc::clone(&progress);
+        let thread_path = path.clone();
+        let handle = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(interval) {
+                if let Err(err) = write_progress(&thread_path, &snapshot(&thread_progress)) {
+                    warn!("Unable to write progress: {}", err);
+                }
+            }
+        });
+
+        ProgressReporter {
+            progress,
+            phase_started: Mutex::new(Instant::now()),
+            path: Some(path),
+            stop_sender: Some(stop_sender),
+            handle: Some(handle),
+        }
+    }
+
+    /// Constructs a `ProgressReporter` which does not write progress anywhere.
+    pub fn disabled() -> Self {
+        ProgressReporter {
+            progress: Arc::new(Mutex::new(Progress::default())),
+            phase_started: Mutex::new(Instant::now()),
+            path: None,
+            stop_sender: None,
+            handle: None,
+        }
+    }
+
+    /// Returns a copy of the current progress. Use the accessors for single counters in code
+    /// which runs for every record, as this copies all of the metrics.
+    pub fn progress(&self) -> Progress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Returns the number of output records emitted so far.
+    pub fn output_records(&self) -> u64 {
+        self.progress.lock().unwrap().metrics.output_records
+    }
+
+    /// Moves on to the given phase, adding the time spent in the previous phase to the metrics.
+    pub fn set_phase(&self, phase: Phase) {
+        let mut phase_started = self.phase_started.lock().unwrap();
+        let elapsed = phase_started.elapsed();
+        *phase_started = Instant::now();
+        self.update(|progress| {
+            progress.metrics.add_phase_time(progress.phase, elapsed);
+            progress.phase = phase;
+        });
+    }
+
+    pub fn add_records(&self, count: u64) {
+        self.update(|progress| progress.records_processed += count);
+    }
+
+    pub fn add_input_records(&self, count: u64) {
+        self.update(|progress| progress.metrics.input_records += count);
+    }
+
+    pub fn add_output_records(&self, count: u64) {
+        self.update(|progress| progress.metrics.output_records += count);
+    }
+
+    /// Raises the peak record count to `count`, if it is higher than the current peak.
+    pub fn update_peak_records(&self, count: u64) {
+        self.update(|progress| {
+            if count > progress.metrics.peak_records {
+                progress.metrics.peak_records = count;
+            }
+        });
+    }
+
+    pub fn add_bytes(&self, count: u64) {
+        self.update(|progress| progress.bytes_consumed += count);
+    }
+
+    /// Updates the progress in place. Updates happen for every record, so the progress is only
+    /// copied when a report is written.
+    fn update<F: FnOnce(&mut Progress)>(&self, f: F) {
+        f(&mut self.progress.lock().unwrap());
+    }
+
+    /// Marks the payload as done, stops the background thread and writes the final progress.
+    pub fn finish(mut self) -> Result<()> {
+        self.set_phase(Phase::Done);
+        self.stop_sender.take();
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                warn!("Progress reporting thread panicked.");
+            }
+        }
+        if let Some(ref path) = self.path {
+            write_progress(path, &snapshot(&self.progress))?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies the progress to write a report of it, stamped with the current time.
+fn snapshot(progress: &Mutex<Progress>) -> Progress {
+    let mut snapshot = progress.lock().unwrap().clone();
+    snapshot.time_updated = Utc::now().timestamp();
+    snapshot
+}
+
+/// Writes the progress to a temporary file and then moves it into place.
+fn write_progress(path: &Path, progress: &Progress) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&tmp_path).chain_err(|| "Error creating progress file.")?;
+        serde_json::to_writer(&mut file, progress).chain_err(|| "Error writing progress.")?;
+        file.flush().chain_err(|| "Error writing progress.")?;
+    }
+    fs::rename(&tmp_path, path).chain_err(|| "Error replacing progress file.")?;
+    Ok(())
+}
+
+/// A struct implementing `Read` which counts the bytes read from a source as consumed input.
+pub struct ProgressReader<'a, R> {
+    source: R,
+    reporter: &'a ProgressReporter,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(source: R, reporter: &'a ProgressReporter) -> Self {
+        ProgressReader { source, reporter }
+    }
+}
+
+impl<'a, R: Read> Read for ProgressReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+        let bytes_read = self.source.read(buf)?;
+        self.reporter.add_bytes(bytes_read as u64);
+        Ok(bytes_read)
+    }
+}
+
+/// A struct implementing `EmitIntermediate` and `EmitFinal` which counts every emitted value as an
+/// output record before passing it on to another emitter.
+///
+/// Emitted intermediate pairs are also counted as processed records.
+pub struct ProgressEmitter<'a, E> {
+    emitter: E,
+    reporter: &'a ProgressReporter,
+}
+
+impl<'a, E> ProgressEmitter<'a, E> {
+    pub fn new(emitter: E, reporter: &'a ProgressReporter) -> Self {
+        ProgressEmitter { emitter, reporter }
+    }
+}
+
+impl<'a, K, V, E> EmitIntermediate<K, V> for ProgressEmitter<'a, E>
+where
+    K: Serialize,
+    V: Serialize,
+    E: EmitIntermediate<K, V>,
+{
+    fn emit(&mut self, key: K, value: V) -> Result<()> {
+        self.emitter.emit(key, value)?;
+        self.reporter.update(|progress| {
+            progress.records_processed += 1;
+            progress.metrics.output_records += 1;
+        });
+        Ok(())
+    }
+}
+
+impl<'a, V, E> EmitFinal<V> for ProgressEmitter<'a, E>
+where
+    V: Serialize,
+    E: EmitFinal<V>,
+{
+    fn emit(&mut self, value: V) -> Result<()> {
+        self.emitter.emit(value)?;
+        self.reporter.add_output_records(1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emitter::{FinalVecEmitter, IntermediateVecEmitter};
+    use std::env;
+    use std::io::Cursor;
+    use uuid::Uuid;
+
+    #[test]
+    fn progress_emitter_counts_records() {
+        let reporter = ProgressReporter::disabled();
+        let mut vec: Vec<(String, u64)> = Vec::new();
+
+        {
+            let mut emitter = ProgressEmitter::new(IntermediateVecEmitter::new(&mut vec), &reporter);
+            emitter.emit("foo".to_owned(), 1).unwrap();
+            emitter.emit("bar".to_owned(), 2).unwrap();
+        }
+
+        assert_eq!(2, vec.len());
+        assert_eq!(2, reporter.progress().records_processed);
+        assert_eq!(2, reporter.progress().metrics.output_records);
+        assert_eq!(2, reporter.output_records());
+    }
+
+    #[test]
+    fn progress_emitter_counts_final_values() {
+        let reporter = ProgressReporter::disabled();
+        let mut vec: Vec<u64> = Vec::new();
+
+        {
+            let mut emitter = ProgressEmitter::new(FinalVecEmitter::new(&mut vec), &reporter);
+            emitter.emit(1).unwrap();
+        }
+
+        assert_eq!(0, reporter.progress().records_processed);
+        assert_eq!(1, reporter.progress().metrics.output_records);
+    }
+
+    #[test]
+    fn set_phase_records_phase_time() {
+        let reporter = ProgressReporter::disabled();
+        reporter.set_phase(Phase::Map);
+        thread::sleep(Duration::from_millis(20));
+        reporter.set_phase(Phase::WritingOutput);
+
+        let metrics = reporter.progress().metrics;
+        assert!(metrics.map_ms >= 20);
+        assert_eq!(0, metrics.write_output_ms);
+    }
+
+    #[test]
+    fn peak_records_only_increases() {
+        let reporter = ProgressReporter::disabled();
+        reporter.update_peak_records(5);
+        reporter.update_peak_records(3);
+
+        assert_eq!(5, reporter.progress().metrics.peak_records);
+    }
+
+    #[test]
+    fn progress_reader_counts_bytes() {
+        let reporter = ProgressReporter::disabled();
+        let mut output = String::new();
+
+        ProgressReader::new(Cursor::new("foobar"), &reporter)
+            .read_to_string(&mut output)
+            .unwrap();
+
+        assert_eq!("foobar", output);
+        assert_eq!(6, reporter.progress().bytes_consumed);
+    }
+
+    #[test]
+    fn finish_writes_final_progress() {
+        let path = env::temp_dir().join(format!("progress_{}.json", Uuid::new_v4()));
+        let reporter = ProgressReporter::new(&path, Duration::from_secs(60));
+        reporter.set_phase(Phase::Map);
+        reporter.add_records(3);
+
+        reporter.finish().unwrap();
+
+        let file = File::open(&path).unwrap();
+        let progress: Progress = serde_json::from_reader(file).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Phase::Done, progress.phase);
+        assert_eq!(3, progress.records_processed);
+    }
+}
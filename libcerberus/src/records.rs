@@ -0,0 +1,257 @@
+//! Module for splitting map input into records of a given format.
+//!
+//! For record formats, the input chunk given to the payload is split into records, and the `Map`
+//! is run once for each record. The value of each `MapInputKV` is the record encoded as JSON,
+//! which can be parsed into a typed struct using `MapInputKV::record`.
+
+use std::str::FromStr;
+
+use csv;
+use serde_json;
+use serde_json::{Map, Value};
+
+use errors::*;
+use mapper::MapInputKV;
+
+/// `InputDataKind` is the format of the input data of a map task.
+///
+/// It mirrors the `InputDataKind` enum of the heracles datatypes, and can be parsed from the names
+/// used there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputDataKind {
+    /// Plain text. The whole input chunk is given to the `Map` at once.
+    TextNewlines,
+    /// Comma separated values, one record per line.
+    Csv,
+    /// Tab separated values, one record per line.
+    Tsv,
+    /// One JSON document per line.
+    JsonLines,
+}
+
+impl FromStr for InputDataKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            // Tasks created before the input kind was passed on are treated as plain text.
+            "UNDEFINED" | "DATA_TEXT_NEWLINES" => Ok(InputDataKind::TextNewlines),
+            "DATA_CSV" => Ok(InputDataKind::Csv),
+            "DATA_TSV" => Ok(InputDataKind::Tsv),
+            "DATA_JSON_LINES" => Ok(InputDataKind::JsonLines),
+            _ => Err(format!("Unknown input data kind {:?}.", s).into()),
+        }
+    }
+}
+
+/// `split_records` splits a `MapInputKV` into one `MapInputKV` per record of the given format.
+///
+/// For `TextNewlines`, the input is returned unchanged.
+///
+/// CSV and TSV records are encoded as JSON objects keyed by the fields of the input's header. If
+/// the input has no header, they are encoded as JSON arrays of strings instead. JSON Lines records
+/// are validated and passed on as they are. Empty lines are skipped.
+pub fn split_records(input: MapInputKV, kind: InputDataKind) -> Result<Vec<MapInputKV>> {
+    let values = match kind {
+        InputDataKind::TextNewlines => return Ok(vec![input]),
+        InputDataKind::Csv => split_delimited(&input, b',', true)?,
+        InputDataKind::Tsv => split_delimited(&input, b'\t', false)?,
+        InputDataKind::JsonLines => split_json_lines(&input)?,
+    };
+
+    Ok(values
+        .into_iter()
+        .map(|value| MapInputKV {
+            key: input.key.clone(),
+            value,
+            tag: input.tag.clone(),
+            header: input.header.clone(),
+        })
+        .collect())
+}
+
+fn delimited_reader(data: &str, delimiter: u8, quoting: bool) -> csv::Reader<&[u8]> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .quoting(quoting)
+        .from_reader(data.as_bytes())
+}
+
+fn split_delimited(input: &MapInputKV, delimiter: u8, quoting: bool) -> Result<Vec<String>> {
+    let header: Option<Vec<String>> = match delimited_reader(&input.header, delimiter, quoting)
+        .records()
+        .next()
+    {
+        Some(record) => Some(
+            record
+                .chain_err(|| "Error parsing input header.")?
+                .iter()
+                .map(|field| field.to_owned())
+                .collect(),
+        ),
+        None => None,
+    };
+
+    let mut ret = Vec::new();
+    for (index, record) in delimited_reader(&input.value, delimiter, quoting)
+        .records()
+        .enumerate()
+    {
+        let record = record.chain_err(|| format!("Error parsing input record {}.", index + 1))?;
+        let value = match header {
+            Some(ref header) => {
+                if header.len() != record.len() {
+                    return Err(format!(
+                        "Input record {} has {} fields, but the header has {}.",
+                        index + 1,
+                        record.len(),
+                        header.len()
+                    ).into());
+                }
+                let mut object = Map::new();
+                for (name, field) in header.iter().zip(record.iter()) {
+                    object.insert(name.to_owned(), Value::String(field.to_owned()));
+                }
+                Value::Object(object)
+            }
+            None => Value::Array(
+                record
+                    .iter()
+                    .map(|field| Value::String(field.to_owned()))
+                    .collect(),
+            ),
+        };
+        ret.push(serde_json::to_string(&value).chain_err(|| "Error encoding input record.")?);
+    }
+    Ok(ret)
+}
+
+fn split_json_lines(input: &MapInputKV) -> Result<Vec<String>> {
+    let mut ret = Vec::new();
+    for (index, line) in input.value.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        serde_json::from_str::<Value>(line)
+            .chain_err(|| format!("Error parsing JSON on input line {}.", index + 1))?;
+        ret.push(line.to_owned());
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Person {
+        name: String,
+        age: String,
+    }
+
+    fn input_with_header(value: &str, header: &str) -> MapInputKV {
+        MapInputKV {
+            key: "people".to_owned(),
+            value: value.to_owned(),
+            tag: String::new(),
+            header: header.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_input_data_kind() {
+        assert_eq!(InputDataKind::Csv, "DATA_CSV".parse().unwrap());
+        assert_eq!(
+            InputDataKind::TextNewlines,
+            "UNDEFINED".parse().unwrap()
+        );
+        assert!("csv".parse::<InputDataKind>().is_err());
+    }
+
+    #[test]
+    fn text_input_is_unchanged() {
+        let input = MapInputKV::new("foo".to_owned(), "bar\nbaz\n".to_owned());
+
+        let records = split_records(input, InputDataKind::TextNewlines).unwrap();
+
+        assert_eq!(1, records.len());
+        assert_eq!("bar\nbaz\n", records[0].value);
+    }
+
+    #[test]
+    fn csv_records_with_header() {
+        let input = input_with_header("alice,30\n\"smith, bob\",25\n", "name,age");
+
+        let records = split_records(input, InputDataKind::Csv).unwrap();
+
+        assert_eq!(2, records.len());
+        assert_eq!("people", records[0].key);
+        assert_eq!(
+            Person {
+                name: "smith, bob".to_owned(),
+                age: "25".to_owned(),
+            },
+            records[1].record().unwrap()
+        );
+    }
+
+    #[test]
+    fn csv_records_without_header() {
+        let input = input_with_header("alice,30\n", "");
+
+        let records = split_records(input, InputDataKind::Csv).unwrap();
+
+        assert_eq!(r#"["alice","30"]"#, records[0].value);
+    }
+
+    #[test]
+    fn csv_record_with_wrong_field_count() {
+        let input = input_with_header("alice,30,extra\n", "name,age");
+
+        assert!(split_records(input, InputDataKind::Csv).is_err());
+    }
+
+    #[test]
+    fn tsv_records_with_header() {
+        let input = input_with_header("alice\t30\n", "name\tage");
+
+        let records = split_records(input, InputDataKind::Tsv).unwrap();
+
+        assert_eq!(
+            Person {
+                name: "alice".to_owned(),
+                age: "30".to_owned(),
+            },
+            records[0].record().unwrap()
+        );
+    }
+
+    #[test]
+    fn json_lines_records() {
+        let input = MapInputKV::new(
+            "people".to_owned(),
+            "{\"name\":\"alice\",\"age\":\"30\"}\n\n{\"name\":\"bob\",\"age\":\"25\"}\n".to_owned(),
+        );
+
+        let records = split_records(input, InputDataKind::JsonLines).unwrap();
+
+        assert_eq!(2, records.len());
+        assert_eq!(
+            Person {
+                name: "bob".to_owned(),
+                age: "25".to_owned(),
+            },
+            records[1].record().unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_json_lines_record() {
+        let input = MapInputKV::new("people".to_owned(), "{\"name\":".to_owned());
+
+        assert!(split_records(input, InputDataKind::JsonLines).is_err());
+    }
+}
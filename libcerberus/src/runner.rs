@@ -1,19 +1,27 @@
+use std::fs::File;
 use std::hash;
-use std::io::{stdin, stdout};
+use std::io::{stdin, stdout, BufWriter, Read, Write};
+use std::time::Duration;
 
 use chrono::prelude::*;
 use clap::{App, Arg, ArgMatches, SubCommand};
 use uuid::Uuid;
 
-use super::VERSION;
+use super::{PROTOCOL_VERSION, VERSION};
+use dryrun::{dry_run, parse_partition_count, DEFAULT_CHUNK_SIZE, DEFAULT_SAMPLE_SIZE};
 use emitter::IntermediateVecEmitter;
 use errors::*;
 use io::*;
+use manifest::write_manifest;
 use mapper::Map;
-use partition::{HashPartitioner, Partition, PartitionInputPairs};
-use reducer::Reduce;
-use serialise::{FinalOutputObject, FinalOutputObjectEmitter, IntermediateOutputObject,
-                IntermediateOutputObjectEmitter};
+use partition::{HashPartitioner, HashPartitioningEmitter, Partition, PartitionInputPairs};
+use progress::{Phase, ProgressEmitter, ProgressReader, ProgressReporter,
+               DEFAULT_REPORT_INTERVAL_SECS};
+use records::{split_records, InputDataKind};
+use reducer::{Reduce, ReduceInputKV};
+use serialise::{FinalOutputObject, FinalOutputObjectEmitter, FinalOutputStreamEmitter,
+                IntermediateOutputObject, IntermediateOutputObjectEmitter,
+                IntermediateOutputStreamEmitter};
 
 /// `UserImplRegistry` tracks the user's implementations of Map, Reduce, etc.
 ///
@@ -90,18 +98,130 @@ pub fn parse_command_line<'a>() -> ArgMatches<'a> {
     let app = App::new(payload_name)
         .version(VERSION.unwrap_or("unknown"))
         .subcommand(
-            SubCommand::with_name("map").arg(
-                Arg::with_name("partition_count")
-                    .long("partition_count")
-                    .required(true)
-                    .takes_value(true),
-            ),
+            SubCommand::with_name("map")
+                .arg(
+                    Arg::with_name("partition_count")
+                        .long("partition_count")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("input_kind")
+                        .long("input_kind")
+                        .help("Format of the input data, as named by the InputDataKind enum.")
+                        .takes_value(true)
+                        .default_value("DATA_TEXT_NEWLINES"),
+                )
+                .arg(stream_arg())
+                .arg(progress_file_arg())
+                .arg(protocol_version_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("reduce")
+                .arg(
+                    Arg::with_name("output_kind")
+                        .long("output_kind")
+                        .help("Format of the final output, as named by the OutputDataKind enum.")
+                        .takes_value(true)
+                        .conflicts_with("stream"),
+                )
+                .arg(
+                    Arg::with_name("manifest_file")
+                        .long("manifest_file")
+                        .help("File to write a manifest of the final output to.")
+                        .takes_value(true)
+                        .conflicts_with("stream"),
+                )
+                .arg(stream_arg())
+                .arg(progress_file_arg())
+                .arg(protocol_version_arg()),
         )
-        .subcommand(SubCommand::with_name("reduce"))
-        .subcommand(SubCommand::with_name("sanity-check"));
+        .subcommand(SubCommand::with_name("sanity-check"))
+        .subcommand(
+            SubCommand::with_name("dry-run")
+                .about("Runs the map operation locally over an input file and summarises it.")
+                .arg(
+                    Arg::with_name("input")
+                        .long("input")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("partitions")
+                        .long("partitions")
+                        .takes_value(true)
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("input_kind")
+                        .long("input_kind")
+                        .help("Format of the input data, as named by the InputDataKind enum.")
+                        .takes_value(true)
+                        .default_value("DATA_TEXT_NEWLINES"),
+                )
+                .arg(
+                    Arg::with_name("chunk_size")
+                        .long("chunk_size")
+                        .help("Maximum size of each input chunk in bytes.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("samples")
+                        .long("samples")
+                        .help("Number of sample pairs to print for each partition.")
+                        .takes_value(true),
+                ),
+        );
     app.get_matches()
 }
 
+fn stream_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("stream")
+        .long("stream")
+        .help("Stream output records as they are emitted, one JSON object per line.")
+}
+
+fn progress_file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("progress_file")
+        .long("progress_file")
+        .help("File to periodically write progress reports to.")
+        .takes_value(true)
+}
+
+fn protocol_version_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("protocol_version")
+        .long("protocol_version")
+        .help("Version of the input/output protocol used by the worker.")
+        .takes_value(true)
+}
+
+/// Checks that the protocol version given by the worker, if any, matches the one supported by
+/// this payload.
+fn check_protocol_version(matches: &ArgMatches) -> Result<()> {
+    if let Some(value) = matches.value_of("protocol_version") {
+        let worker_version = value
+            .parse::<u32>()
+            .chain_err(|| format!("Invalid protocol version {:?}.", value))?;
+        if worker_version != PROTOCOL_VERSION {
+            return Err(
+                ErrorKind::ProtocolVersionMismatch(worker_version, PROTOCOL_VERSION).into(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Creates a `ProgressReporter` writing to the file given by the `progress_file` argument, if
+/// there is one.
+fn progress_reporter(matches: &ArgMatches) -> ProgressReporter {
+    match matches.value_of("progress_file") {
+        Some(path) => {
+            ProgressReporter::new(path, Duration::from_secs(DEFAULT_REPORT_INTERVAL_SECS))
+        }
+        None => ProgressReporter::disabled(),
+    }
+}
+
 /// `run` begins the primary operations of the payload, and delegates to sub-functions.
 ///
 /// # Arguments
@@ -115,21 +235,56 @@ where
     <M as Map>::Key: hash::Hash,
 {
     match matches.subcommand_name() {
-        Some("map") => run_map(
-            registry.mapper,
-            matches
-                .subcommand_matches("map")
-                .unwrap()
+        Some("map") => {
+            let map_matches = matches.subcommand_matches("map").unwrap();
+            check_protocol_version(map_matches)?;
+            let partition_count = map_matches
                 .value_of("partition_count")
                 .unwrap()
                 .parse::<u64>()
-                .unwrap(),
-        ),
-        Some("reduce") => run_reduce(registry.reducer),
+                .unwrap();
+            let input_kind = map_matches
+                .value_of("input_kind")
+                .unwrap()
+                .parse::<InputDataKind>()?;
+            let reporter = progress_reporter(map_matches);
+            if map_matches.is_present("stream") {
+                run_map_streaming(registry.mapper, partition_count, input_kind, &reporter)?;
+            } else {
+                run_map(registry.mapper, partition_count, input_kind, &reporter)?;
+            }
+            reporter.finish()
+        }
+        Some("reduce") => {
+            let reduce_matches = matches.subcommand_matches("reduce").unwrap();
+            check_protocol_version(reduce_matches)?;
+            // The default is set here rather than in clap, as clap considers arguments with a
+            // default value to always be present, which would conflict with `--stream`.
+            let output_kind = reduce_matches
+                .value_of("output_kind")
+                .unwrap_or("OUTPUT_JSON")
+                .parse::<OutputDataKind>()?;
+            let reporter = progress_reporter(reduce_matches);
+            if reduce_matches.is_present("stream") {
+                run_reduce_streaming(registry.reducer, &reporter)?;
+            } else {
+                run_reduce(
+                    registry.reducer,
+                    output_kind,
+                    reduce_matches.value_of("manifest_file"),
+                    &reporter,
+                )?;
+            }
+            reporter.finish()
+        }
         Some("sanity-check") => {
             run_sanity_check();
             Ok(())
         }
+        Some("dry-run") => {
+            let dry_run_matches = matches.subcommand_matches("dry-run").unwrap();
+            run_dry_run(registry.mapper, dry_run_matches)
+        }
         None => {
             eprintln!("{}", matches.usage());
             Ok(())
@@ -139,21 +294,46 @@ where
     }
 }
 
-fn run_map<M>(mapper: &M, partition_count: u64) -> Result<()>
+fn run_map<M>(
+    mapper: &M,
+    partition_count: u64,
+    input_kind: InputDataKind,
+    reporter: &ProgressReporter,
+) -> Result<()>
 where
     M: Map,
     <M as Map>::Key: hash::Hash,
 {
-    let mut source = stdin();
+    let mut source = ProgressReader::new(stdin(), reporter);
     let mut sink = stdout();
+    reporter.set_phase(Phase::ReadingInput);
     let input_kv = read_map_input(&mut source).chain_err(|| "Error getting input to map.")?;
 
     let mut pairs_vec: Vec<(M::Key, M::Value)> = Vec::new();
 
+    let records = split_records(input_kv, input_kind)
+        .chain_err(|| "Error splitting map input into records.")?;
+
+    reporter.add_input_records(records.len() as u64);
+    reporter.set_phase(Phase::Map);
+    for record in records {
+        let pairs_before = pairs_vec.len();
+        mapper
+            .map(
+                record,
+                ProgressEmitter::new(IntermediateVecEmitter::new(&mut pairs_vec), reporter),
+            )
+            .chain_err(|| "Error running map operation.")?;
+        reporter.update_peak_records((pairs_vec.len() - pairs_before) as u64);
+    }
     mapper
-        .map(input_kv, IntermediateVecEmitter::new(&mut pairs_vec))
-        .chain_err(|| "Error running map operation.")?;
+        .finish(ProgressEmitter::new(
+            IntermediateVecEmitter::new(&mut pairs_vec),
+            reporter,
+        ))
+        .chain_err(|| "Error finishing map operation.")?;
 
+    reporter.set_phase(Phase::Partition);
     let mut output_object = IntermediateOutputObject::<M::Key, M::Value>::default();
 
     HashPartitioner::new(partition_count)
@@ -163,35 +343,174 @@ where
         )
         .chain_err(|| "Error partitioning map output")?;
 
+    reporter.set_phase(Phase::WritingOutput);
     write_map_output(&mut sink, &output_object)
         .chain_err(|| "Error writing map output to stdout.")?;
     Ok(())
 }
 
-fn run_reduce<R: Reduce>(reducer: &R) -> Result<()> {
-    let mut source = stdin();
+fn run_map_streaming<M>(
+    mapper: &M,
+    partition_count: u64,
+    input_kind: InputDataKind,
+    reporter: &ProgressReporter,
+) -> Result<()>
+where
+    M: Map,
+    <M as Map>::Key: hash::Hash,
+{
+    let mut source = ProgressReader::new(stdin(), reporter);
+    let stdout = stdout();
+    let mut sink = BufWriter::new(stdout.lock());
+    reporter.set_phase(Phase::ReadingInput);
+    let input_kv = read_map_input(&mut source).chain_err(|| "Error getting input to map.")?;
+
+    let records = split_records(input_kv, input_kind)
+        .chain_err(|| "Error splitting map input into records.")?;
+    let partitioner = HashPartitioner::new(partition_count);
+
+    write_protocol_header(&mut sink).chain_err(|| "Error writing map output to stdout.")?;
+    reporter.add_input_records(records.len() as u64);
+    reporter.set_phase(Phase::Map);
+    for record in records {
+        let pairs_before = reporter.output_records();
+        mapper
+            .map(
+                record,
+                ProgressEmitter::new(
+                    HashPartitioningEmitter::new(
+                        &partitioner,
+                        IntermediateOutputStreamEmitter::new(&mut sink),
+                    ),
+                    reporter,
+                ),
+            )
+            .chain_err(|| "Error running map operation.")?;
+        reporter.update_peak_records(reporter.output_records() - pairs_before);
+    }
+    mapper
+        .finish(ProgressEmitter::new(
+            HashPartitioningEmitter::new(
+                &partitioner,
+                IntermediateOutputStreamEmitter::new(&mut sink),
+            ),
+            reporter,
+        ))
+        .chain_err(|| "Error finishing map operation.")?;
+
+    sink.flush()
+        .chain_err(|| "Error writing map output to stdout.")?;
+    Ok(())
+}
+
+fn run_reduce<R: Reduce>(
+    reducer: &R,
+    output_kind: OutputDataKind,
+    manifest_file: Option<&str>,
+    reporter: &ProgressReporter,
+) -> Result<()> {
+    let mut source = ProgressReader::new(stdin(), reporter);
     let mut sink = stdout();
+    reporter.set_phase(Phase::ReadingInput);
     let input_kvs = read_reduce_input(&mut source).chain_err(|| "Error getting input to reduce.")?;
-    
 
     let mut output_objects = Vec::new();
 
+    reporter.add_input_records(input_kvs.len() as u64);
+    reporter.set_phase(Phase::Reduce);
     for input_kv in input_kvs {
         let mut output_object = FinalOutputObject::<R::Value>::default();
         let key = input_kv.key.clone();
+        reporter.update_peak_records(input_kv.values.len() as u64);
         reducer
-            .reduce(input_kv, FinalOutputObjectEmitter::new(&mut output_object))
+            .reduce(
+                input_kv,
+                ProgressEmitter::new(FinalOutputObjectEmitter::new(&mut output_object), reporter),
+            )
             .chain_err(|| "Error running reduce operation.")?;
         output_object.key = key;
         output_objects.push(output_object);
+        reporter.add_records(1);
     }
-   
 
-    write_reduce_output(&mut sink, &output_objects)
+    reporter.set_phase(Phase::WritingOutput);
+    let manifest = write_reduce_output_as(&mut sink, &output_objects, output_kind)
         .chain_err(|| "Error writing reduce output to stdout.")?;
+    if let Some(path) = manifest_file {
+        write_manifest(path, &manifest).chain_err(|| "Error writing reduce output manifest.")?;
+    }
+    Ok(())
+}
+
+fn run_reduce_streaming<R: Reduce>(reducer: &R, reporter: &ProgressReporter) -> Result<()> {
+    let mut source = ProgressReader::new(stdin(), reporter);
+    let stdout = stdout();
+    let mut sink = BufWriter::new(stdout.lock());
+    reporter.set_phase(Phase::ReadingInput);
+    let input_kvs: Vec<ReduceInputKV<R::Value>> =
+        read_reduce_input(&mut source).chain_err(|| "Error getting input to reduce.")?;
+
+    write_protocol_header(&mut sink).chain_err(|| "Error writing reduce output to stdout.")?;
+    reporter.add_input_records(input_kvs.len() as u64);
+    reporter.set_phase(Phase::Reduce);
+    for input_kv in input_kvs {
+        let key = input_kv.key.clone();
+        reporter.update_peak_records(input_kv.values.len() as u64);
+        reducer
+            .reduce(
+                input_kv,
+                ProgressEmitter::new(FinalOutputStreamEmitter::new(&key, &mut sink), reporter),
+            )
+            .chain_err(|| "Error running reduce operation.")?;
+        reporter.add_records(1);
+    }
+
+    sink.flush()
+        .chain_err(|| "Error writing reduce output to stdout.")?;
+    Ok(())
+}
+
+fn run_dry_run<M>(mapper: &M, matches: &ArgMatches) -> Result<()>
+where
+    M: Map,
+    <M as Map>::Key: hash::Hash,
+{
+    let path = matches.value_of("input").unwrap();
+    let partition_count = parse_partition_count(matches.value_of("partitions").unwrap())?;
+    let input_kind = matches
+        .value_of("input_kind")
+        .unwrap()
+        .parse::<InputDataKind>()?;
+    let chunk_size = match matches.value_of("chunk_size") {
+        Some(value) => value.parse::<u64>().chain_err(|| "Invalid chunk size.")?,
+        None => DEFAULT_CHUNK_SIZE,
+    };
+    let sample_size = match matches.value_of("samples") {
+        Some(value) => value
+            .parse::<usize>()
+            .chain_err(|| "Invalid sample count.")?,
+        None => DEFAULT_SAMPLE_SIZE,
+    };
+
+    let mut data = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut data))
+        .chain_err(|| format!("Error reading input file {}.", path))?;
+
+    let report = dry_run(
+        mapper,
+        path,
+        &data,
+        partition_count,
+        input_kind,
+        chunk_size,
+        sample_size,
+    )?;
+    print!("{}", report);
     Ok(())
 }
 
 fn run_sanity_check() {
     println!("sanity located");
+    println!("protocol_version {}", PROTOCOL_VERSION);
 }
@@ -1,7 +1,10 @@
 use std::collections::HashMap;
+use std::io::Write;
 
 use serde::Serialize;
+use serde_json;
 
+use super::PROTOCOL_VERSION;
 use emitter::{EmitFinal, EmitPartitionedIntermediate};
 use errors::*;
 
@@ -19,23 +22,84 @@ where
 
 /// `IntermediateOutputObject` is a struct comprising a collection of `IntermediateOutputArray`s,
 /// representing a partition of the output of a map operation, ready to be serialised to JSON.
-#[derive(Debug, Default, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct IntermediateOutputObject<K, V>
 where
     K: Default + Serialize,
     V: Default + Serialize,
 {
+    pub protocol_version: u32,
     pub partitions: HashMap<u64, Vec<IntermediateOutputPair<K, V>>>,
 }
 
+impl<K, V> Default for IntermediateOutputObject<K, V>
+where
+    K: Default + Serialize,
+    V: Default + Serialize,
+{
+    fn default() -> Self {
+        IntermediateOutputObject {
+            protocol_version: PROTOCOL_VERSION,
+            partitions: HashMap::new(),
+        }
+    }
+}
+
 /// `FinalOutputObject` is a struct comprising a collection of serialisable values representing the
 /// entire output of a reduce operation, ready to be serialised to JSON.
-#[derive(Debug, Default, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct FinalOutputObject<V: Default + Serialize> {
+    pub protocol_version: u32,
     pub key: String,
     pub values: Vec<V>,
 }
 
+impl<V: Default + Serialize> Default for FinalOutputObject<V> {
+    fn default() -> Self {
+        FinalOutputObject {
+            protocol_version: PROTOCOL_VERSION,
+            key: String::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+/// `ProtocolHeader` is written as the first line of streamed output, so that the reader can check
+/// the protocol version before reading any records.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ProtocolHeader {
+    pub protocol_version: u32,
+}
+
+impl Default for ProtocolHeader {
+    fn default() -> Self {
+        ProtocolHeader {
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+}
+
+/// `IntermediateOutputRecord` is a single partitioned key-value pair as written by an
+/// `IntermediateOutputStreamEmitter`.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct IntermediateOutputRecord<K, V>
+where
+    K: Default + Serialize,
+    V: Default + Serialize,
+{
+    pub partition: u64,
+    pub key: K,
+    pub value: V,
+}
+
+/// `FinalOutputRecord` is a single value outputted from a reduce operation, as written by a
+/// `FinalOutputStreamEmitter`.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct FinalOutputRecord<'a, V: Default + Serialize> {
+    pub key: &'a str,
+    pub value: V,
+}
+
 /// A struct implementing `EmitIntermediate` which emits to an `IntermediateOutputObject`.
 pub struct IntermediateOutputObjectEmitter<'a, K, V>
 where
@@ -101,10 +165,87 @@ impl<'a, V: Default + Serialize> EmitFinal<V> for FinalOutputObjectEmitter<'a, V
     }
 }
 
+/// A struct implementing `EmitPartitionedIntermediate` which streams each key-value pair to a
+/// sink as soon as it is emitted.
+///
+/// Each pair is written as an `IntermediateOutputRecord` JSON object on its own line.
+pub struct IntermediateOutputStreamEmitter<'a, W: Write + 'a> {
+    sink: &'a mut W,
+}
+
+impl<'a, W: Write> IntermediateOutputStreamEmitter<'a, W> {
+    /// Constructs a new `IntermediateOutputStreamEmitter` with a mutable reference to a given
+    /// sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - A mutable reference to the `Write` to receive the emitted values.
+    pub fn new(sink: &'a mut W) -> Self {
+        IntermediateOutputStreamEmitter { sink }
+    }
+}
+
+impl<'a, K, V, W> EmitPartitionedIntermediate<K, V> for IntermediateOutputStreamEmitter<'a, W>
+where
+    K: Default + Serialize,
+    V: Default + Serialize,
+    W: Write,
+{
+    fn emit(&mut self, partition: u64, key: K, value: V) -> Result<()> {
+        let record = IntermediateOutputRecord {
+            partition,
+            key,
+            value,
+        };
+        serde_json::to_writer(&mut *self.sink, &record).chain_err(|| "Error writing to sink.")?;
+        self.sink
+            .write_all(b"\n")
+            .chain_err(|| "Error writing to sink.")?;
+        Ok(())
+    }
+}
+
+/// A struct implementing `EmitFinal` which streams each value to a sink as soon as it is emitted.
+///
+/// Each value is written as a `FinalOutputRecord` JSON object on its own line.
+pub struct FinalOutputStreamEmitter<'a, W: Write + 'a> {
+    key: &'a str,
+    sink: &'a mut W,
+}
+
+impl<'a, W: Write> FinalOutputStreamEmitter<'a, W> {
+    /// Constructs a new `FinalOutputStreamEmitter` with a mutable reference to a given sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The intermediate key the emitted values belong to.
+    /// * `sink` - A mutable reference to the `Write` to receive the emitted values.
+    pub fn new(key: &'a str, sink: &'a mut W) -> Self {
+        FinalOutputStreamEmitter { key, sink }
+    }
+}
+
+impl<'a, V, W> EmitFinal<V> for FinalOutputStreamEmitter<'a, W>
+where
+    V: Default + Serialize,
+    W: Write,
+{
+    fn emit(&mut self, value: V) -> Result<()> {
+        let record = FinalOutputRecord {
+            key: self.key,
+            value,
+        };
+        serde_json::to_writer(&mut *self.sink, &record).chain_err(|| "Error writing to sink.")?;
+        self.sink
+            .write_all(b"\n")
+            .chain_err(|| "Error writing to sink.")?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json;
     use std::collections::HashSet;
 
     // Test that the JSON serialisation of IntermediateOutputObject matches the libcerberus JSON
@@ -136,13 +277,14 @@ mod tests {
         );
 
         let output = IntermediateOutputObject {
+            protocol_version: 1,
             partitions: partitions,
         };
         let mut output_set = HashSet::new();
         let expected_json_string1 =
-            r#"{"partitions":{"0":[{"key":"foo_intermediate","value":"bar"},{"key":"foo_intermediate","value":"baz"}],"1":[{"key":"foo_intermediate2","value":"bar"}]}}"#;
+            r#"{"protocol_version":1,"partitions":{"0":[{"key":"foo_intermediate","value":"bar"},{"key":"foo_intermediate","value":"baz"}],"1":[{"key":"foo_intermediate2","value":"bar"}]}}"#;
         let expected_json_string2 =
-            r#"{"partitions":{"1":[{"key":"foo_intermediate2","value":"bar"}],"0":[{"key":"foo_intermediate","value":"bar"},{"key":"foo_intermediate","value":"baz"}]}}"#;
+            r#"{"protocol_version":1,"partitions":{"1":[{"key":"foo_intermediate2","value":"bar"}],"0":[{"key":"foo_intermediate","value":"bar"},{"key":"foo_intermediate","value":"baz"}]}}"#;
         output_set.insert(expected_json_string1.to_owned());
         output_set.insert(expected_json_string2.to_owned());
 
@@ -155,10 +297,12 @@ mod tests {
     #[test]
     fn final_output_object_json_format() {
         let output = FinalOutputObject {
+            protocol_version: 1,
             key: "test".to_string(),
             values: vec!["barbaz", "bazbar"],
         };
-        let expected_json_string = r#"{"key":"test","values":["barbaz","bazbar"]}"#;
+        let expected_json_string =
+            r#"{"protocol_version":1,"key":"test","values":["barbaz","bazbar"]}"#;
 
         let json_string = serde_json::to_string(&output).unwrap();
 
@@ -180,6 +324,7 @@ mod tests {
         );
 
         let expected_output = IntermediateOutputObject {
+            protocol_version: PROTOCOL_VERSION,
             partitions: partitions,
         };
 
@@ -195,6 +340,7 @@ mod tests {
     fn final_output_emitter_works() {
         let mut output = FinalOutputObject::default();
         let expected_output = FinalOutputObject {
+            protocol_version: PROTOCOL_VERSION,
             key: String::new(),
             values: vec!["foo", "bar"],
         };
@@ -207,4 +353,36 @@ mod tests {
 
         assert_eq!(expected_output, output);
     }
+
+    #[test]
+    fn intermediate_output_stream_emitter_works() {
+        let mut output: Vec<u8> = Vec::new();
+        let expected_output = r#"{"partition":0,"key":"foo","value":"bar"}
+{"partition":3,"key":"baz","value":"qux"}
+"#;
+
+        {
+            let mut emitter = IntermediateOutputStreamEmitter::new(&mut output);
+            emitter.emit(0, "foo", "bar").unwrap();
+            emitter.emit(3, "baz", "qux").unwrap();
+        }
+
+        assert_eq!(expected_output, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn final_output_stream_emitter_works() {
+        let mut output: Vec<u8> = Vec::new();
+        let expected_output = r#"{"key":"test","value":"foo"}
+{"key":"test","value":"bar"}
+"#;
+
+        {
+            let mut emitter = FinalOutputStreamEmitter::new("test", &mut output);
+            emitter.emit("foo").unwrap();
+            emitter.emit("bar").unwrap();
+        }
+
+        assert_eq!(expected_output, String::from_utf8(output).unwrap());
+    }
 }
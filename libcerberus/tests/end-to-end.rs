@@ -1,11 +1,16 @@
 /// This is a set of integration tests which run against a dummy payload binary living in
 /// `libcerberus/src/bin/end-to-end.rs`.
 
+extern crate uuid;
+
 use std::env;
+use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
+use uuid::Uuid;
+
 const TEST_BIN_NAME: &str = "end-to-end";
 
 // This can't be a one-liner because cargo sometimes runs integration tests from
@@ -29,14 +34,39 @@ fn run_sanity_check() {
         .unwrap();
     let output_str = String::from_utf8(output.stdout).unwrap();
 
-    assert_eq!("sanity located\n", output_str);
+    assert_eq!("sanity located\nprotocol_version 2\n", output_str);
+}
+
+#[test]
+fn run_dry_run() {
+    let input_dir = env::temp_dir().join(format!("libcerberus-dry-run-{}", Uuid::new_v4()));
+    fs::create_dir(&input_dir).unwrap();
+    let input_path = input_dir.join("input.txt");
+    fs::write(&input_path, "bar zar\nbar\n").unwrap();
+    let expected_output = r#"1 chunks, 1 records, 3 pairs
+partition 0: 3 pairs, 2 distinct keys
+    "bar" => "test"
+    "zar" => "test"
+    "bar" => "test"
+"#;
+
+    let output = Command::new(get_bin_path())
+        .args(&["dry-run", "--partitions", "1", "--input"])
+        .arg(&input_path)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&input_dir).unwrap();
+    let output_str = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(expected_output, output_str);
 }
 
 #[test]
 fn run_map_valid_input() {
     let json_input = r#"{"key":"foo","value":"bar zar"}"#;
     let expected_output =
-        r#"{"partitions":{"0":[{"key":"bar","value":"test"},{"key":"zar","value":"test"}]}}"#;
+        r#"{"protocol_version":2,"partitions":{"0":[{"key":"bar","value":"test"},{"key":"zar","value":"test"}]}}"#;
 
     let mut child = Command::new(get_bin_path())
         .args(&["map", "--partition_count", "1"])
@@ -62,6 +92,36 @@ fn run_map_valid_input() {
     assert_eq!(expected_output, output_str);
 }
 
+#[test]
+fn run_map_streaming_valid_input() {
+    let json_input = r#"{"key":"foo","value":"bar zar"}"#;
+    let expected_output = r#"{"protocol_version":2}
+{"partition":0,"key":"bar","value":"test"}
+{"partition":0,"key":"zar","value":"test"}
+"#;
+
+    let mut child = Command::new(get_bin_path())
+        .args(&["map", "--partition_count", "1", "--stream"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(json_input.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let output_str = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(expected_output, output_str);
+}
+
 #[test]
 fn run_map_invalid_input() {
     let bad_input = r#"foo"#;
@@ -89,7 +149,7 @@ fn run_map_invalid_input() {
 #[test]
 fn run_reduce_valid_input() {
     let json_input = r#"[{"key":"foo","values":["bar","baz"]}]"#;
-    let expected_output = r#"[{"key":"foo","values":["barbaz"]}]"#;
+    let expected_output = r#"[{"protocol_version":2,"key":"foo","values":["barbaz"]}]"#;
 
     let mut child = Command::new(get_bin_path())
         .arg("reduce")
@@ -113,6 +173,86 @@ fn run_reduce_valid_input() {
     assert_eq!(expected_output, output_str);
 }
 
+#[test]
+fn run_reduce_streaming_valid_input() {
+    let json_input = r#"[{"key":"foo","values":["bar","baz"]}]"#;
+    let expected_output = "{\"protocol_version\":2}\n{\"key\":\"foo\",\"value\":\"barbaz\"}\n";
+
+    let mut child = Command::new(get_bin_path())
+        .args(&["reduce", "--stream"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(json_input.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let output_str = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(expected_output, output_str);
+}
+
+#[test]
+fn run_map_protocol_version_mismatch() {
+    let json_input = r#"{"key":"foo","value":"bar zar"}"#;
+
+    let mut child = Command::new(get_bin_path())
+        .args(&["map", "--partition_count", "1", "--protocol_version", "999"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(json_input.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let error_str = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(false, output.status.success());
+    assert!(error_str.contains("Protocol version mismatch"));
+}
+
+#[test]
+fn run_reduce_text_output() {
+    let json_input = r#"[{"key":"foo","values":["bar","baz"]}]"#;
+    let expected_output = "foo\tbarbaz\n";
+
+    let mut child = Command::new(get_bin_path())
+        .args(&["reduce", "--output_kind", "OUTPUT_TEXT"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(json_input.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let output_str = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(expected_output, output_str);
+}
+
 #[test]
 fn run_reduce_invalid_input() {
     let json_input = r#"foo"#;
@@ -41,13 +41,15 @@ func NewAMQP(addr string) (*AMQPConnection, error) {
 
 	queueName := settings.String("broker.queue_name")
 
+	// The arguments must match the ones the workers declare the queue with.
+	args := amqp.Table{"x-max-priority": int32(settings.Int("broker.max_priority"))}
 	if _, err = ch.QueueDeclare(
 		queueName,
 		true,
 		false,
 		false,
 		false,
-		nil,
+		args,
 	); err != nil {
 		return nil, errors.Wrap(err, "unable to declare a queue")
 	}
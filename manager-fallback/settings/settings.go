@@ -31,7 +31,8 @@ func Init() error {
 }
 
 func setDefaults() {
-	settings.SetDefault("broker.queue_name", "heracles_tasks")
+	settings.SetDefault("broker.queue_name", "heracles_priority_tasks")
+	settings.SetDefault("broker.max_priority", 10)
 	settings.SetDefault("broker.address", "")
 	settings.SetDefault("state.backend", "file")
 	settings.SetDefault("state.location", "")
@@ -43,6 +44,7 @@ func setDefaults() {
 func setOptions() {
 	flag.String("broker.queue_name", "", "queue name")
 	flag.String("broker.address", "", "address of the broker")
+	flag.Int("broker.max_priority", 0, "highest task priority, must match the workers")
 	flag.String("state.backend", "", "backend of the state store")
 	flag.String("state.location", "", "path to the file store")
 	flag.Int("scheduler.input_chunk_size", 64*1024*1024*1024, "chunk size")
@@ -1,8 +1,9 @@
+use std::cmp;
 use std::net::SocketAddr;
 
 use lapin::channel::{BasicProperties, BasicPublishOptions, Channel, QueueDeclareOptions};
 use lapin::client::{Client, ConnectionOptions};
-use lapin::types::FieldTable;
+use lapin::types::{AMQPValue, FieldTable};
 use protobuf::Message;
 use tokio::net::TcpStream;
 use tokio::prelude::*;
@@ -13,6 +14,7 @@ use settings::SETTINGS;
 pub struct AMQPBrokerConnection {
     channel: Channel<TcpStream>,
     queue_name: String,
+    max_priority: u8,
 }
 
 impl BrokerConnection for AMQPBrokerConnection {
@@ -20,39 +22,53 @@ impl BrokerConnection for AMQPBrokerConnection {
     ///
     /// The `Option<bool>` returned represents whether the message was acked (`Some(true)`), nacked
     /// (`Some(false)`), or the queue is not a confirm queue (`None`).
-    fn send<'a>(&'a self, task: &'a Task) -> Box<Future<Item = Option<bool>, Error = Error> + 'a> {
-        let task_id = task.get_id().to_string();
-        let ret = future::lazy(move || future::done(task.write_to_bytes()))
-            .map_err(|e| e.context(BrokerError::TaskSerialisationFailure { task_id }))
-            .from_err()
-            .and_then(move |bytes| {
-                self.channel
-                    .basic_publish(
-                        "",
-                        &self.queue_name,
-                        &bytes,
-                        &BasicPublishOptions::default(),
-                        BasicProperties::default(),
-                    )
-                    .from_err()
-            });
+    fn send(&self, task: &Task) -> Box<Future<Item = Option<bool>, Error = Error> + Send> {
+        let bytes = match task.write_to_bytes() {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                let task_id = task.get_id().to_string();
+                return Box::new(future::err(
+                    err.context(BrokerError::TaskSerialisationFailure { task_id })
+                        .into(),
+                ));
+            }
+        };
+        // Tasks with a priority higher than the queue supports are treated as the most urgent.
+        let priority = cmp::min(task.get_priority(), u64::from(self.max_priority)) as u8;
+        let ret = self.channel
+            .basic_publish(
+                "",
+                &self.queue_name,
+                &bytes,
+                &BasicPublishOptions::default(),
+                BasicProperties::default().with_priority(priority),
+            )
+            .from_err();
         Box::new(ret)
     }
 }
 
 pub fn connect(addr: SocketAddr) -> impl Future<Item = AMQPBrokerConnection, Error = Error> {
-    let queue_name = SETTINGS.read().unwrap().get("broker_queue_name").unwrap();
+    let queue_name = SETTINGS.read().unwrap().get("broker.queue_name").unwrap();
+    let max_priority: u8 = SETTINGS.read().unwrap().get("broker.max_priority").unwrap();
     let queue_options = QueueDeclareOptions {
         durable: true,
         ..Default::default()
     };
+    // Workers declare the queue with the same arguments, as a queue can't be redeclared with
+    // different ones.
+    let mut queue_args = FieldTable::new();
+    queue_args.insert(
+        "x-max-priority".to_string(),
+        AMQPValue::LongInt(i32::from(max_priority)),
+    );
 
     TcpStream::connect(&addr)
         .and_then(|stream| Client::connect(stream, &ConnectionOptions::default()))
         .and_then(|(client, _)| client.create_channel())
         .and_then(move |channel| {
             channel
-                .queue_declare(queue_name, &queue_options, &FieldTable::new())
+                .queue_declare(queue_name, &queue_options, &queue_args)
                 .and_then(move |_| {
                     info!("AMQP queue `{}` successfully declared.", queue_name);
                     future::ok(channel)
@@ -63,6 +79,7 @@ pub fn connect(addr: SocketAddr) -> impl Future<Item = AMQPBrokerConnection, Err
             future::ok(AMQPBrokerConnection {
                 channel,
                 queue_name: queue_name.to_string(),
+                max_priority,
             })
         })
 }
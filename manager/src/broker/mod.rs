@@ -6,7 +6,9 @@ use futures::Future;
 use heracles_proto::datatypes::Task;
 
 pub trait BrokerConnection {
-    fn send<'a>(&'a self, &'a Task) -> Box<Future<Item = Option<bool>, Error = Error> + 'a>;
+    /// Sends a `Task` to the broker. The task is serialised straight away, so the returned future
+    /// does not borrow it.
+    fn send(&self, task: &Task) -> Box<Future<Item = Option<bool>, Error = Error> + Send>;
 }
 
 #[derive(Debug, Fail)]
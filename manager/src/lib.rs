@@ -6,6 +6,7 @@
         unused_import_braces, unused_qualifications)]
 #![feature(conservative_impl_trait)]
 
+extern crate cerberus;
 extern crate chrono;
 extern crate clap;
 extern crate config;
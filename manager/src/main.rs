@@ -1,16 +1,29 @@
 extern crate chrono;
 extern crate failure;
 extern crate fern;
+extern crate futures;
 extern crate heracles_manager;
 #[macro_use]
 extern crate log;
 extern crate tokio;
 
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
 use failure::*;
+use futures::sync::mpsc;
 use tokio::prelude::*;
 
+use heracles_manager::broker::BrokerConnection;
+use heracles_manager::scheduler::Scheduler;
 use heracles_manager::settings::SETTINGS;
-use heracles_manager::{broker, optparse, settings};
+use heracles_manager::state::FileStore;
+use heracles_manager::{broker, optparse, server, settings};
+
+/// Number of tasks which can be on their way to the broker at once. Tasks are kept in the
+/// scheduler's priority queue until then, so this is kept small.
+const TASK_CHANNEL_BUFFER: usize = 16;
 
 fn main() {
     if let Err(err) = run() {
@@ -27,14 +40,51 @@ fn run() -> Result<(), Error> {
     let arg_matches = optparse::parse_cmd_options();
     settings::init(&arg_matches)?;
 
-    let broker_addr = SETTINGS.read().unwrap().get("broker_address")?;
+    let broker_addr = SETTINGS.read().unwrap().get("broker.address")?;
     let broker_conn = broker::amqp::connect(broker_addr);
 
+    let state_location: String = SETTINGS.read().unwrap().get("state.location")?;
+    let store = Arc::new(FileStore::new(&PathBuf::from(state_location))?);
+
+    let (task_sender, task_receiver) = mpsc::channel(TASK_CHANNEL_BUFFER);
+    let scheduler = Scheduler::new(task_sender, store)?;
+    scheduler.recover()?;
+    let recurring_scheduler = scheduler.clone();
+    thread::spawn(move || recurring_scheduler.run_recurring_jobs());
+    let broker_scheduler = scheduler.clone();
+    let _server = server::Server::new(scheduler)?;
+
     info!("Starting main event loop.");
-    // We give this an empty future so that it will never terminate and continue driving other
-    // futures to completion.
-    tokio::run(future::empty());
-    Ok(())
+    // Tasks sent by the scheduler are forwarded to the broker until sending fails.
+    tokio::run(
+        broker_conn
+            .map_err(|err| error!("{}", err))
+            .and_then(move |conn| {
+                task_receiver.for_each(move |task| {
+                    conn.send(&task).then(move |res| match res {
+                        Ok(Some(false)) => {
+                            warn!("Task {} was nacked by the broker.", task.get_id());
+                            Ok(())
+                        }
+                        Ok(_) => {
+                            debug!("Task {} sent to the broker.", task.get_id());
+                            Ok(())
+                        }
+                        Err(err) => {
+                            error!("Unable to send task {}: {}", task.get_id(), err);
+                            Err(())
+                        }
+                    })
+                })
+            }),
+    );
+
+    // Running jobs are failed rather than left waiting for tasks which were never sent. The server
+    // keeps reporting the status of jobs, but new jobs are rejected until the manager is restarted.
+    broker_scheduler.close_broker();
+    loop {
+        thread::park();
+    }
 }
 
 fn init_logger() -> Result<(), Error> {
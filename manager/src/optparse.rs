@@ -29,6 +29,36 @@ Each chunk corresponds to one map task, so this can be used to scale the job.",
                 .long("broker-queue-name")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("intermediate_directory")
+                .help("Directory to store the intermediate files of map tasks in.")
+                .long("intermediate-directory")
+                .long_help(
+                    "Directory to store the intermediate files of map tasks in.
+It must be shared between the manager and all workers.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("state.location")
+                .help("Directory to store the state of jobs and tasks in.")
+                .long("state-location")
+                .long_help(
+                    "Directory to store the state of jobs and tasks in.
+It must be shared between the manager and all workers.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("task_timeout")
+                .help("Time (in seconds) a task may run for before it is retried.")
+                .long("task-timeout")
+                .long_help(
+                    "Time (in seconds) a task may run for before it is retried.
+Tasks running for longer are assumed to be lost along with their worker. 0 disables the timeout.",
+                )
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("server_port")
                 .help("Port on which the gRPC server is running")
@@ -0,0 +1,237 @@
+//! Module for limiting how much of the cluster each client can use at once.
+//!
+//! Every client has a quota on the number of jobs it can have running, and on the total input
+//! size and number of tasks of those jobs. Jobs which would take a client over its quota are
+//! rejected when they are scheduled. Quotas are read from the `quota` settings, and can be
+//! overridden for a client under `quota.clients.<client_id>`. A limit of 0 means no limit.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use config::ConfigError;
+
+use settings::SETTINGS;
+
+use super::*;
+
+/// `Quota` is the most a client can use at once.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quota {
+    pub max_jobs: u64,
+    pub max_input_bytes: u64,
+    pub max_tasks: u64,
+}
+
+impl Quota {
+    /// Reads the quota of a client from the settings.
+    pub fn for_client(client_id: &str) -> Result<Quota, SchedulerError> {
+        let settings = SETTINGS.read().unwrap();
+        let limit = |name: &str| -> Result<u64, SchedulerError> {
+            let client_key = format!("quota.clients.{}.{}", client_id, name);
+            match settings.get(&client_key) {
+                Ok(limit) => Ok(limit),
+                Err(ConfigError::NotFound(_)) => Ok(settings
+                    .get(&format!("quota.{}", name))
+                    .context(SchedulerErrorKind::SettingsReadFailed)?),
+                Err(err) => Err(err.context(SchedulerErrorKind::SettingsReadFailed).into()),
+            }
+        };
+        Ok(Quota {
+            max_jobs: limit("max_jobs")?,
+            max_input_bytes: limit("max_input_bytes")?,
+            max_tasks: limit("max_tasks")?,
+        })
+    }
+
+    /// Checks that `usage` is within the quota, returning a description of the first limit
+    /// exceeded otherwise.
+    fn check(&self, usage: &Usage) -> Result<(), String> {
+        let limits = [
+            ("running jobs", usage.jobs, self.max_jobs),
+            ("bytes of input", usage.input_bytes, self.max_input_bytes),
+            ("tasks", usage.tasks, self.max_tasks),
+        ];
+        for &(name, used, max) in &limits {
+            if max != 0 && used > max {
+                return Err(format!("{} {} exceeds the limit of {}", used, name, max));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `Usage` is how much of the cluster the running jobs of a client use.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub jobs: u64,
+    pub input_bytes: u64,
+    pub tasks: u64,
+}
+
+impl Usage {
+    /// Returns the usage of a single job which has been split into `plan`.
+    pub fn of_job(plan: &splitting::TaskPlan) -> Usage {
+        let input_bytes = plan.map_tasks
+            .iter()
+            .map(|task| {
+                let chunk = task.get_input_chunk();
+                chunk.get_end_byte().saturating_sub(chunk.get_start_byte())
+            })
+            .sum();
+        Usage {
+            jobs: 1,
+            input_bytes,
+            tasks: (plan.map_tasks.len() + plan.reduce_tasks.len()) as u64,
+        }
+    }
+
+    fn add(&mut self, other: &Usage) {
+        self.jobs += other.jobs;
+        self.input_bytes += other.input_bytes;
+        self.tasks += other.tasks;
+    }
+
+    fn subtract(&mut self, other: &Usage) {
+        self.jobs = self.jobs.saturating_sub(other.jobs);
+        self.input_bytes = self.input_bytes.saturating_sub(other.input_bytes);
+        self.tasks = self.tasks.saturating_sub(other.tasks);
+    }
+}
+
+/// `Admission` keeps track of the usage of each client, and admits jobs which fit in the quota
+/// of their client.
+#[derive(Default)]
+pub struct Admission {
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl Admission {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Checks that a client can run another job before the job is split, so a client at its job
+    /// limit is turned away without reading the job's input. The size of the job is only known
+    /// once it is split, so the rest of the quota is checked by `admit`.
+    pub fn precheck(&self, client_id: &str, quota: &Quota) -> Result<(), SchedulerError> {
+        let usage = self.usage.lock().unwrap();
+        let mut client_usage = usage.get(client_id).cloned().unwrap_or_default();
+        client_usage.add(&Usage {
+            jobs: 1,
+            ..Default::default()
+        });
+        check_quota(client_id, &client_usage, quota)
+    }
+
+    /// Admits a job of a client if it fits in the client's quota. The usage of the job counts
+    /// against the quota until it is released with `release`.
+    pub fn admit(&self, client_id: &str, job: &Usage, quota: &Quota) -> Result<(), SchedulerError> {
+        let mut usage = self.usage.lock().unwrap();
+        let mut client_usage = usage.get(client_id).cloned().unwrap_or_default();
+        client_usage.add(job);
+        check_quota(client_id, &client_usage, quota)?;
+        usage.insert(client_id.to_string(), client_usage);
+        Ok(())
+    }
+
+    /// Stops counting the usage of a finished job against its client's quota.
+    pub fn release(&self, client_id: &str, job: &Usage) {
+        let mut usage = self.usage.lock().unwrap();
+        let remove = match usage.get_mut(client_id) {
+            Some(client_usage) => {
+                client_usage.subtract(job);
+                client_usage.jobs == 0
+            }
+            None => false,
+        };
+        if remove {
+            usage.remove(client_id);
+        }
+    }
+}
+
+/// Returns a `QuotaExceeded` error if the usage of a client is over its quota.
+fn check_quota(client_id: &str, usage: &Usage, quota: &Quota) -> Result<(), SchedulerError> {
+    if let Err(details) = quota.check(usage) {
+        let details = format!("Client \"{}\" is over its quota: {}", client_id, details);
+        return Err(err_msg(details)
+            .context(SchedulerErrorKind::QuotaExceeded)
+            .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(input_bytes: u64, tasks: u64) -> Usage {
+        Usage {
+            jobs: 1,
+            input_bytes,
+            tasks,
+        }
+    }
+
+    #[test]
+    fn test_admit_within_quota() {
+        let admission = Admission::new();
+        let quota = Quota {
+            max_jobs: 2,
+            ..Default::default()
+        };
+
+        assert!(admission.admit("client", &job(10, 2), &quota).is_ok());
+        assert!(admission.admit("client", &job(10, 2), &quota).is_ok());
+        let err = admission.admit("client", &job(10, 2), &quota).unwrap_err();
+        assert_eq!(SchedulerErrorKind::QuotaExceeded, err.kind());
+
+        // Other clients have their own quota.
+        assert!(admission.admit("other", &job(10, 2), &quota).is_ok());
+    }
+
+    #[test]
+    fn test_precheck() {
+        let admission = Admission::new();
+        let quota = Quota {
+            max_jobs: 1,
+            max_tasks: 10,
+            ..Default::default()
+        };
+
+        // Only the job limit is checked before a job is split.
+        assert!(admission.precheck("client", &quota).is_ok());
+        assert!(admission.admit("client", &job(0, 4), &quota).is_ok());
+        let err = admission.precheck("client", &quota).unwrap_err();
+        assert_eq!(SchedulerErrorKind::QuotaExceeded, err.kind());
+        assert!(admission.precheck("other", &quota).is_ok());
+    }
+
+    #[test]
+    fn test_release() {
+        let admission = Admission::new();
+        let quota = Quota {
+            max_tasks: 10,
+            ..Default::default()
+        };
+
+        assert!(admission.admit("client", &job(0, 8), &quota).is_ok());
+        assert!(admission.admit("client", &job(0, 8), &quota).is_err());
+        admission.release("client", &job(0, 8));
+        assert!(admission.admit("client", &job(0, 8), &quota).is_ok());
+    }
+
+    #[test]
+    fn test_input_bytes_limit() {
+        let quota = Quota {
+            max_input_bytes: 100,
+            ..Default::default()
+        };
+
+        assert!(quota.check(&job(100, 1)).is_ok());
+        assert_eq!(
+            Err("101 bytes of input exceeds the limit of 100".to_string()),
+            quota.check(&job(101, 1))
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Module for parsing cron expressions and working out when they next fire.
+//!
+//! Expressions have the five standard fields: minute, hour, day of month, month and day of week,
+//! and are evaluated in UTC. As in cron, when both the day of month and the day of week are
+//! restricted, a day matching either of them fires.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+use super::*;
+
+/// How many years ahead to look for the next firing before giving up. Expressions such as
+/// `0 0 30 2 *` never fire.
+const MAX_YEARS_AHEAD: i32 = 5;
+
+/// `CronSchedule` is a parsed cron expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CronSchedule {
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+}
+
+/// `Field` is the set of values one field of a cron expression matches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Field {
+    values: u64,
+    /// Whether the field starts with `*`, which matters when matching days.
+    any: bool,
+}
+
+impl Field {
+    fn parse(field: &str, min: u32, max: u32) -> Result<Field, String> {
+        let mut values = 0;
+        for part in field.split(',') {
+            let (range, step) = match part.find('/') {
+                Some(index) => (&part[..index], parse_number(&part[index + 1..])?),
+                None => (part, 1),
+            };
+            if step == 0 {
+                return Err(format!("step of \"{}\" must be above 0", part));
+            }
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else {
+                match range.find('-') {
+                    Some(index) => (
+                        parse_number(&range[..index])?,
+                        parse_number(&range[index + 1..])?,
+                    ),
+                    None => {
+                        let value = parse_number(range)?;
+                        // A step after a single value runs to the end of the field, as in cron.
+                        (value, if part.contains('/') { max } else { value })
+                    }
+                }
+            };
+            if start < min || end > max || start > end {
+                return Err(format!(
+                    "\"{}\" is outside of the range {}-{}",
+                    part, min, max
+                ));
+            }
+            let mut value = start;
+            while value <= end {
+                values |= 1 << value;
+                value += step;
+            }
+        }
+        Ok(Field {
+            values,
+            any: field.starts_with('*'),
+        })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.values & (1 << value) != 0
+    }
+}
+
+fn parse_number(number: &str) -> Result<u32, String> {
+    number
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a number", number))
+}
+
+impl CronSchedule {
+    /// Parses a cron expression, returning an `InvalidCronExpression` error if it is malformed.
+    pub fn parse(expression: &str) -> Result<CronSchedule, SchedulerError> {
+        CronSchedule::parse_fields(expression).map_err(|details| {
+            let details = format!("Invalid cron expression \"{}\": {}", expression, details);
+            err_msg(details)
+                .context(SchedulerErrorKind::InvalidCronExpression)
+                .into()
+        })
+    }
+
+    fn parse_fields(expression: &str) -> Result<CronSchedule, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        }
+        let mut days_of_week = Field::parse(fields[4], 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if days_of_week.matches(7) {
+            days_of_week.values |= 1;
+        }
+        Ok(CronSchedule {
+            minutes: Field::parse(fields[0], 0, 59)?,
+            hours: Field::parse(fields[1], 0, 23)?,
+            days_of_month: Field::parse(fields[2], 1, 31)?,
+            months: Field::parse(fields[3], 1, 12)?,
+            days_of_week,
+        })
+    }
+
+    /// Returns the first time after `timestamp` the expression fires, or `None` if it never does.
+    pub fn next_after(&self, timestamp: u64) -> Option<u64> {
+        let start = NaiveDateTime::from_timestamp(timestamp as i64, 0);
+        let last_year = start.year() + MAX_YEARS_AHEAD;
+        let mut time = start.date().and_hms(start.hour(), start.minute(), 0) + Duration::minutes(1);
+
+        while time.year() <= last_year {
+            if !self.months.matches(time.month()) {
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                time = NaiveDate::from_ymd(year, month, 1).and_hms(0, 0, 0);
+            } else if !self.matches_day(&time.date()) {
+                time = time.date().succ().and_hms(0, 0, 0);
+            } else if !self.hours.matches(time.hour()) {
+                time = time.date().and_hms(time.hour(), 0, 0) + Duration::hours(1);
+            } else if !self.minutes.matches(time.minute()) {
+                time = time + Duration::minutes(1);
+            } else {
+                return Some(time.timestamp() as u64);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, date: &NaiveDate) -> bool {
+        let day_of_month = self.days_of_month.matches(date.day());
+        let day_of_week = self.days_of_week
+            .matches(date.weekday().num_days_from_sunday());
+        if self.days_of_month.any || self.days_of_week.any {
+            day_of_month && day_of_week
+        } else {
+            day_of_month || day_of_week
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> u64 {
+        NaiveDate::from_ymd(year, month, day)
+            .and_hms(hour, minute, 0)
+            .timestamp() as u64
+    }
+
+    fn next_after(expression: &str, after: u64) -> Option<u64> {
+        CronSchedule::parse(expression).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn test_every_minute() {
+        let now = timestamp(2018, 3, 14, 10, 30) + 15;
+        assert_eq!(
+            Some(timestamp(2018, 3, 14, 10, 31)),
+            next_after("* * * * *", now)
+        );
+    }
+
+    #[test]
+    fn test_nightly() {
+        let now = timestamp(2018, 12, 31, 23, 0);
+        assert_eq!(
+            Some(timestamp(2019, 1, 1, 2, 30)),
+            next_after("30 2 * * *", now)
+        );
+        // Firing times are never repeated.
+        let fired = timestamp(2019, 1, 1, 2, 30);
+        assert_eq!(
+            Some(timestamp(2019, 1, 2, 2, 30)),
+            next_after("30 2 * * *", fired)
+        );
+    }
+
+    #[test]
+    fn test_ranges_and_steps() {
+        let now = timestamp(2018, 3, 14, 10, 46);
+        assert_eq!(
+            Some(timestamp(2018, 3, 14, 11, 0)),
+            next_after("*/15 9-17 * * *", now)
+        );
+        assert_eq!(
+            Some(timestamp(2018, 3, 14, 10, 50)),
+            next_after("10,20-50/10 * * * *", now)
+        );
+    }
+
+    #[test]
+    fn test_days() {
+        // 14th of March 2018 is a Wednesday.
+        let now = timestamp(2018, 3, 14, 12, 0);
+        // Weekdays only.
+        assert_eq!(
+            Some(timestamp(2018, 3, 15, 0, 0)),
+            next_after("0 0 * * 1-5", now)
+        );
+        // Sundays, as either 0 or 7.
+        assert_eq!(
+            Some(timestamp(2018, 3, 18, 0, 0)),
+            next_after("0 0 * * 7", now)
+        );
+        // Either the 1st of the month or a Friday.
+        assert_eq!(
+            Some(timestamp(2018, 3, 16, 0, 0)),
+            next_after("0 0 1 * 5", now)
+        );
+    }
+
+    #[test]
+    fn test_never_fires() {
+        assert_eq!(None, next_after("0 0 30 2 *", timestamp(2018, 1, 1, 0, 0)));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for expression in &["* * * *", "60 * * * *", "* * 0 * *", "*/0 * * * *", "a * * * *"] {
+            let err = CronSchedule::parse(expression).unwrap_err();
+            assert_eq!(SchedulerErrorKind::InvalidCronExpression, err.kind());
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Module for running jobs only once the jobs they depend on are done.
+//!
+//! A job with `depends_on` set waits in `JOB_WAITING` until all of the jobs it depends on are
+//! done. If any of them fails or is cancelled, the job fails without running, which in turn fails
+//! the jobs depending on it.
+
+use super::*;
+
+/// `Readiness` is whether a job can run, judging by the jobs it depends on.
+#[derive(Debug, PartialEq)]
+pub enum Readiness {
+    /// All of the jobs are done.
+    Ready,
+    /// Some of the jobs have not finished yet.
+    Waiting,
+    /// One of the jobs failed or was cancelled, so the job can never run.
+    Failed(String),
+}
+
+/// Returns whether a job depending on `dependencies` can run.
+pub fn readiness(dependencies: &[Job]) -> Readiness {
+    let mut ready = true;
+    for dependency in dependencies {
+        match dependency.get_status() {
+            JobStatus::JOB_DONE => {}
+            JobStatus::JOB_FAILED => {
+                return Readiness::Failed(format!(
+                    "Job {} failed: {}",
+                    dependency.get_id(),
+                    dependency.get_failure_details()
+                ))
+            }
+            JobStatus::JOB_CANCELLED => {
+                return Readiness::Failed(format!("Job {} was cancelled", dependency.get_id()))
+            }
+            _ => ready = false,
+        }
+    }
+    if ready {
+        Readiness::Ready
+    } else {
+        Readiness::Waiting
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: &str, status: JobStatus) -> Job {
+        let mut job = Job::new();
+        job.set_id(id.to_string());
+        job.set_status(status);
+        job
+    }
+
+    #[test]
+    fn test_ready() {
+        let dependencies = vec![
+            job("first", JobStatus::JOB_DONE),
+            job("second", JobStatus::JOB_DONE),
+        ];
+        assert_eq!(Readiness::Ready, readiness(&dependencies));
+    }
+
+    #[test]
+    fn test_waiting() {
+        let dependencies = vec![
+            job("first", JobStatus::JOB_DONE),
+            job("second", JobStatus::JOB_IN_PROGRESS),
+            job("third", JobStatus::JOB_WAITING),
+        ];
+        assert_eq!(Readiness::Waiting, readiness(&dependencies));
+    }
+
+    #[test]
+    fn test_failed_dependency() {
+        let mut failed = job("second", JobStatus::JOB_FAILED);
+        failed.set_failure_details("A task failed too many times.".to_string());
+        let dependencies = vec![job("first", JobStatus::JOB_IN_PROGRESS), failed];
+
+        assert_eq!(
+            Readiness::Failed("Job second failed: A task failed too many times.".to_string()),
+            readiness(&dependencies)
+        );
+    }
+
+    #[test]
+    fn test_cancelled_dependency() {
+        let dependencies = vec![job("first", JobStatus::JOB_CANCELLED)];
+        assert_eq!(
+            Readiness::Failed("Job first was cancelled".to_string()),
+            readiness(&dependencies)
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Module for sharing the cluster fairly between clients.
+//!
+//! Each client may only have a limited number of tasks in flight at once, so a client submitting
+//! many jobs can't take over every worker. Clients with queued tasks take turns having them sent
+//! to the broker. Each client has a weight, which scales its limit and how often it gets a turn.
+
+use std::cmp;
+use std::collections::HashMap;
+
+use config::ConfigError;
+
+use settings::SETTINGS;
+
+use super::*;
+
+/// `FairShare` is the policy for sharing the cluster between clients.
+#[derive(Clone, Debug, Default)]
+pub struct FairShare {
+    /// Number of tasks a client with a weight of 1 may have in flight at once. 0 means no limit.
+    pub max_in_flight: usize,
+    /// Weights of the clients. Clients without a weight have a weight of 1.
+    pub weights: HashMap<String, u32>,
+}
+
+impl FairShare {
+    pub fn from_settings() -> Result<Self, SchedulerError> {
+        let settings = SETTINGS.read().unwrap();
+        let max_in_flight = settings
+            .get("fair_share.max_in_flight")
+            .context(SchedulerErrorKind::SettingsReadFailed)?;
+        let weights = match settings.get("fair_share.weights") {
+            Ok(weights) => weights,
+            Err(ConfigError::NotFound(_)) => HashMap::new(),
+            Err(err) => return Err(err.context(SchedulerErrorKind::SettingsReadFailed).into()),
+        };
+        Ok(FairShare {
+            max_in_flight,
+            weights,
+        })
+    }
+
+    /// Returns the weight of a client.
+    pub fn weight(&self, client_id: &str) -> u32 {
+        self.weights
+            .get(client_id)
+            .map_or(1, |&weight| cmp::max(weight, 1))
+    }
+
+    /// Returns whether a client may have another task in flight.
+    pub fn below_limit(&self, client_id: &str, in_flight: usize) -> bool {
+        self.max_in_flight == 0 || in_flight < self.max_in_flight * self.weight(client_id) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_limit() {
+        let mut policy = FairShare {
+            max_in_flight: 2,
+            ..Default::default()
+        };
+        policy.weights.insert("batch".to_string(), 0);
+        policy.weights.insert("interactive".to_string(), 3);
+
+        assert!(policy.below_limit("other", 1));
+        assert!(!policy.below_limit("other", 2));
+        assert!(!policy.below_limit("batch", 2));
+        assert!(policy.below_limit("interactive", 5));
+        assert!(!policy.below_limit("interactive", 6));
+    }
+
+    #[test]
+    fn test_no_limit() {
+        let policy = FairShare::default();
+        assert!(policy.below_limit("client", 1_000_000));
+    }
+}
@@ -1,35 +1,1046 @@
+pub mod admission;
+pub mod cron;
+pub mod dependencies;
+pub mod fairshare;
+pub mod pipeline;
+pub mod queue;
+pub mod recurring;
+pub mod retry;
+pub mod speculation;
+
+use std::collections::HashSet;
 use std::fmt;
 use std::fmt::Display;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
+use chrono::Utc;
 use failure::*;
 use futures::sync::mpsc;
+use futures::{Future, Sink};
+use uuid::Uuid;
 
 use heracles_proto::datatypes::*;
+use self::admission::{Admission, Quota, Usage};
+use self::cron::CronSchedule;
+use self::dependencies::Readiness;
+use self::fairshare::FairShare;
+use self::queue::TaskQueue;
+use splitting;
+use state::{State, StateError, StateErrorKind};
+
+/// How often the pending tasks of a running job are checked.
+const TASKS_POLL_INTERVAL_SECS: u64 = 1;
+/// How often the jobs a waiting job depends on are checked.
+const DEPENDENCIES_POLL_INTERVAL_SECS: u64 = 5;
+/// How often recurring jobs are checked for whether they should be scheduled.
+const RECURRING_JOBS_POLL_INTERVAL_SECS: u64 = 10;
 
+#[derive(Clone)]
 pub struct Scheduler {
-    broker_handle: mpsc::Sender<Task>,
+    admission: Arc<Admission>,
+    /// Set once tasks can no longer be sent to the broker, after which running jobs are failed
+    /// and new jobs are rejected.
+    broker_closed: Arc<AtomicBool>,
+    queue: Arc<TaskQueue>,
+    /// Held while recurring jobs are changed, so a recurring job being scheduled isn't changed
+    /// at the same time.
+    recurring_lock: Arc<Mutex<()>>,
+    /// Held while a job is marked as finished, so a job being cancelled isn't also marked as done
+    /// or failed at the same time.
+    status_lock: Arc<Mutex<()>>,
+    store: Arc<State + Send + Sync>,
 }
 
 impl Scheduler {
-    pub fn new(handle: mpsc::Sender<Task>) -> Self {
-        Scheduler {
-            broker_handle: handle,
+    /// Creates a scheduler, which sends tasks to the broker through `handle` in order of
+    /// priority, sharing the cluster between clients according to the fair share settings.
+    /// Recurring jobs are only scheduled once `run_recurring_jobs` is running.
+    pub fn new(
+        handle: mpsc::Sender<Task>,
+        store: Arc<State + Send + Sync>,
+    ) -> Result<Self, SchedulerError> {
+        let scheduler = Scheduler {
+            admission: Arc::new(Admission::new()),
+            broker_closed: Arc::new(AtomicBool::new(false)),
+            queue: Arc::new(TaskQueue::new(FairShare::from_settings()?)),
+            recurring_lock: Arc::new(Mutex::new(())),
+            status_lock: Arc::new(Mutex::new(())),
+            store,
+        };
+        let dispatcher = scheduler.clone();
+        thread::spawn(move || dispatcher.dispatch(handle));
+        Ok(scheduler)
+    }
+
+    /// Schedules a job, returning the ID assigned to it.
+    ///
+    /// A job which depends on other jobs is saved as waiting, and started on its own thread once
+    /// they are all done. Other jobs are started straight away.
+    pub fn schedule(&self, req: &Job) -> Result<String, SchedulerError> {
+        validate_job(req)?;
+
+        let mut job = req.clone();
+        job.set_id(Uuid::new_v4().to_string());
+        job.set_time_scheduled(Utc::now().timestamp() as u64);
+        let job_id = job.get_id().to_string();
+
+        if job.get_depends_on().is_empty() {
+            self.start(job)?;
+            return Ok(job_id);
+        }
+
+        // Fail early if any of the jobs don't exist.
+        self.get_dependencies(&job)?;
+        job.set_status(JobStatus::JOB_WAITING);
+        self.save_job(&job)?;
+        info!(
+            "Job {} waiting for {} jobs it depends on.",
+            job.get_id(),
+            job.get_depends_on().len()
+        );
+        let scheduler = self.clone();
+        thread::spawn(move || scheduler.run_waiting_job(job));
+        Ok(job_id)
+    }
+
+    /// Loads a job, returning a `JobNotFound` error if there is no job with the given ID.
+    pub fn get_job(&self, job_id: &str) -> Result<Job, SchedulerError> {
+        Ok(self.store.get_job(job_id).map_err(|err| match err.kind() {
+            StateErrorKind::JobNotFound => err.context(SchedulerErrorKind::JobNotFound),
+            _ => err.context(SchedulerErrorKind::StateAccessFailed),
+        })?)
+    }
+
+    /// Loads the tasks of a job. If `task_id` is not empty only that task is returned, or a
+    /// `TaskNotFound` error if the job has no such task.
+    pub fn get_tasks(&self, job_id: &str, task_id: &str) -> Result<Vec<Task>, SchedulerError> {
+        let job = self.get_job(job_id)?;
+        let tasks = self.store
+            .job_tasks(&job)
+            .context(SchedulerErrorKind::StateAccessFailed)?;
+        if task_id.is_empty() {
+            return Ok(tasks);
+        }
+        let tasks: Vec<Task> = tasks
+            .into_iter()
+            .filter(|task| task.get_id() == task_id)
+            .collect();
+        if tasks.is_empty() {
+            return Err(SchedulerErrorKind::TaskNotFound.into());
+        }
+        Ok(tasks)
+    }
+
+    /// Starts running a job.
+    ///
+    /// The job is split into tasks and rejected with a `QuotaExceeded` error if it would take its
+    /// client over its quota. Otherwise the job is saved, and its map tasks are sent to the
+    /// broker. The rest of the job is run on its own thread: the reduce tasks are sent once all
+    /// map tasks are done, and the next stage of the pipeline is scheduled once all reduce tasks
+    /// are done.
+    fn start(&self, mut job: Job) -> Result<(), SchedulerError> {
+        self.check_broker()?;
+        let quota = Quota::for_client(job.get_client_id())?;
+        self.admission.precheck(job.get_client_id(), &quota)?;
+        check_input_directories(&job)?;
+        let plan = splitting::split(&job).context(SchedulerErrorKind::SplittingFailed)?;
+        let usage = Usage::of_job(&plan);
+        self.admission.admit(job.get_client_id(), &usage, &quota)?;
+        if let Err(err) = self.start_job(&mut job, &plan) {
+            self.admission.release(job.get_client_id(), &usage);
+            return Err(err);
+        }
+        info!(
+            "Job {} split into {} map tasks and {} reduce tasks.",
+            job.get_id(),
+            plan.map_tasks.len(),
+            plan.reduce_tasks.len()
+        );
+
+        let map_count = plan.map_tasks.len();
+        let reduce_count = plan.reduce_tasks.len();
+        self.send_tasks(&job, plan.map_tasks);
+
+        let scheduler = self.clone();
+        thread::spawn(move || {
+            scheduler.run_job(job, usage, map_count, reduce_count, plan.reduce_tasks)
+        });
+        Ok(())
+    }
+
+    /// Resumes the jobs which had not finished when the manager last stopped, from the state
+    /// store.
+    ///
+    /// Tasks which no worker has started yet may have been lost along with the task queue, so
+    /// they are sent to the broker again. Tasks which workers were running are left to finish, or
+    /// to time out. A job which can't be resumed is failed, and a job which can't be loaded at all
+    /// is skipped. Pipeline stages which were due to be scheduled are scheduled.
+    pub fn recover(&self) -> Result<(), SchedulerError> {
+        let job_ids = self.store
+            .job_ids()
+            .context(SchedulerErrorKind::StateAccessFailed)?;
+        let mut jobs = Vec::new();
+        for job_id in job_ids {
+            match self.store.get_job(&job_id) {
+                Ok(job) => jobs.push(job),
+                Err(err) => error!("Unable to load job {}, skipping it: {}", job_id, err),
+            }
+        }
+
+        self.schedule_missing_stages(&jobs);
+        for mut job in jobs.into_iter().filter(|job| !is_finished(job)) {
+            match self.recover_job(job.clone()) {
+                Ok(()) => {}
+                Err(ref err) if err.kind() == SchedulerErrorKind::JobCancelled => {
+                    info!("Job {} was cancelled while it was recovered.", job.get_id());
+                }
+                Err(err) => {
+                    error!("Unable to recover job {}: {}", job.get_id(), err);
+                    self.fail_job(&mut job, &err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Schedules the next stage of pipelines whose last job is done, but whose next stage wasn't
+    /// scheduled because the manager stopped in between.
+    fn schedule_missing_stages(&self, jobs: &[Job]) {
+        let scheduled: HashSet<(String, u32)> = jobs.iter()
+            .filter(|job| !job.get_pipeline_id().is_empty())
+            .map(|job| (job.get_pipeline_id().to_string(), job.get_stage_index()))
+            .collect();
+        for job in jobs {
+            let next_stage = (
+                pipeline::pipeline_id(job).to_string(),
+                job.get_stage_index() + 1,
+            );
+            if job.get_status() != JobStatus::JOB_DONE || job.get_stages().is_empty()
+                || scheduled.contains(&next_stage)
+            {
+                continue;
+            }
+            info!(
+                "Recovered job {}, scheduling the next stage of its pipeline.",
+                job.get_id()
+            );
+            if let Err(err) = self.schedule_next_stage(job) {
+                error!(
+                    "Unable to schedule the next stage of pipeline {}: {}",
+                    next_stage.0, err
+                );
+            }
+        }
+    }
+
+    fn recover_job(&self, job: Job) -> Result<(), SchedulerError> {
+        match job.get_status() {
+            JobStatus::JOB_WAITING => {
+                info!("Recovered job {}, waiting for its dependencies.", job.get_id());
+                let scheduler = self.clone();
+                thread::spawn(move || scheduler.run_waiting_job(job));
+            }
+            JobStatus::JOB_IN_PROGRESS => self.resume(job)?,
+            _ => {
+                // The job was still being split, so any of its tasks which were saved are
+                // dropped and it is split again. `start_job` checks that the job wasn't cancelled
+                // in the meantime before saving it.
+                info!("Recovered job {}, splitting it again.", job.get_id());
+                self.store
+                    .cancel_pending_tasks(&job)
+                    .context(SchedulerErrorKind::StateAccessFailed)?;
+                self.start(job)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resumes a job which was in progress, picking up from the phase it was in.
+    fn resume(&self, job: Job) -> Result<(), SchedulerError> {
+        let (map_tasks, reduce_tasks): (Vec<Task>, Vec<Task>) = self.store
+            .job_tasks(&job)
+            .context(SchedulerErrorKind::StateAccessFailed)?
+            .into_iter()
+            // Tasks saved before the job was split again are cancelled.
+            .filter(|task| task.get_status() != TaskStatus::TASK_CANCELLED)
+            .partition(|task| task.get_kind() == TaskKind::MAP);
+        let plan = splitting::TaskPlan {
+            map_tasks,
+            reduce_tasks,
+        };
+        let usage = Usage::of_job(&plan);
+        let quota = Quota::for_client(job.get_client_id())?;
+        self.admission.admit(job.get_client_id(), &usage, &quota)?;
+        let map_count = plan.map_tasks.len();
+        let reduce_count = plan.reduce_tasks.len();
+
+        let pending_map_tasks = self.store
+            .pending_map_tasks(&job)
+            .context(SchedulerErrorKind::StateAccessFailed)?;
+        // None of the reduce tasks are sent until all map tasks are done.
+        let (unsent, reduce_tasks) = if pending_map_tasks.is_empty() {
+            let pending_reduce_tasks = self.store
+                .pending_reduce_tasks(&job)
+                .context(SchedulerErrorKind::StateAccessFailed)?;
+            (unstarted(pending_reduce_tasks), Vec::new())
+        } else {
+            (unstarted(pending_map_tasks), plan.reduce_tasks)
+        };
+        // The tasks may still be waiting in the broker from before the manager stopped, so they
+        // are sent as new attempts, and workers drop the earlier attempts when they receive them.
+        let mut resent = Vec::new();
+        for mut task in unsent {
+            let next = self.next_attempt(&task)?;
+            task.set_failure_details("Sent again after the manager restarted.".to_string());
+            retry::next_attempt(&mut task, next);
+            self.store
+                .save_task(&task)
+                .context(SchedulerErrorKind::StateAccessFailed)?;
+            resent.push(task);
+        }
+        info!(
+            "Recovered job {}, sending {} tasks again.",
+            job.get_id(),
+            resent.len()
+        );
+        self.send_tasks(&job, resent);
+
+        let scheduler = self.clone();
+        thread::spawn(move || {
+            scheduler.run_job(job, usage, map_count, reduce_count, reduce_tasks)
+        });
+        Ok(())
+    }
+
+    /// Starts a waiting job once all of the jobs it depends on are done, or fails it if any of
+    /// them fails.
+    ///
+    /// This blocks until the job is started, so it should be run on its own thread.
+    fn run_waiting_job(&self, mut job: Job) {
+        let result = self.wait_for_dependencies(&job)
+            .and_then(|_| self.start(job.clone()));
+        match result {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == SchedulerErrorKind::JobCancelled => {}
+            Err(err) => {
+                error!("Job {} failed: {}", job.get_id(), err);
+                self.fail_job(&mut job, &err);
+            }
+        }
+    }
+
+    /// Saves a job which will never run as failed.
+    fn fail_job(&self, job: &mut Job, err: &SchedulerError) {
+        job.set_status(JobStatus::JOB_FAILED);
+        job.set_failure_details(failure_details(err));
+        job.set_time_done(Utc::now().timestamp() as u64);
+        if let Err(err) = self.finish_job(job) {
+            error!("Unable to save the status of job {}: {}", job.get_id(), err);
+        }
+    }
+
+    /// Saves the final status of a job, unless the job has been cancelled or has otherwise
+    /// finished since it was loaded. Returns whether the status was saved.
+    fn finish_job(&self, job: &Job) -> Result<bool, SchedulerError> {
+        let _lock = self.status_lock.lock().unwrap();
+        if is_finished(&self.get_job(job.get_id())?) {
+            return Ok(false);
+        }
+        self.save_job(job)?;
+        Ok(true)
+    }
+
+    /// Waits until all of the jobs a job depends on are done. Returns a `DependencyFailed` error
+    /// if any of them fails or is cancelled.
+    fn wait_for_dependencies(&self, job: &Job) -> Result<(), SchedulerError> {
+        loop {
+            self.check_cancelled(job)?;
+            match dependencies::readiness(&self.get_dependencies(job)?) {
+                Readiness::Ready => {
+                    info!("Dependencies of job {} are done, starting it.", job.get_id());
+                    return Ok(());
+                }
+                Readiness::Waiting => {
+                    thread::sleep(Duration::from_secs(DEPENDENCIES_POLL_INTERVAL_SECS))
+                }
+                Readiness::Failed(details) => {
+                    return Err(err_msg(details)
+                        .context(SchedulerErrorKind::DependencyFailed)
+                        .into())
+                }
+            }
+        }
+    }
+
+    /// Loads the jobs a job depends on. Returns a `DependencyNotFound` error if any of them
+    /// doesn't exist.
+    fn get_dependencies(&self, job: &Job) -> Result<Vec<Job>, SchedulerError> {
+        job.get_depends_on()
+            .iter()
+            .map(|job_id| -> Result<Job, SchedulerError> {
+                let dependency = self.store.get_job(job_id).map_err(|err| match err.kind() {
+                    StateErrorKind::JobNotFound => {
+                        err.context(SchedulerErrorKind::DependencyNotFound)
+                    }
+                    _ => err.context(SchedulerErrorKind::StateAccessFailed),
+                })?;
+                Ok(dependency)
+            })
+            .collect()
+    }
+
+    /// Cancels a job which has not finished yet.
+    ///
+    /// The pending tasks of the job are marked as cancelled, so workers drop them when they
+    /// receive them from the broker, and workers stop the tasks of the job they are running. The
+    /// intermediate files of the job are removed.
+    pub fn cancel(&self, job_id: &str) -> Result<(), SchedulerError> {
+        let job = {
+            let _lock = self.status_lock.lock().unwrap();
+            let mut job = self.get_job(job_id)?;
+            if is_finished(&job) {
+                return Err(SchedulerErrorKind::JobAlreadyFinished.into());
+            }
+            job.set_status(JobStatus::JOB_CANCELLED);
+            job.set_time_done(Utc::now().timestamp() as u64);
+            self.save_job(&job)?;
+            job
+        };
+
+        self.clean_up_job(&job)?;
+        self.queue.remove_job(job_id);
+        info!("Job {} cancelled.", job_id);
+        Ok(())
+    }
+
+    /// Removes the pending tasks and the intermediate files of a job which has stopped running.
+    fn clean_up_job(&self, job: &Job) -> Result<(), SchedulerError> {
+        self.store
+            .cancel_pending_tasks(job)
+            .context(SchedulerErrorKind::StateAccessFailed)?;
+        splitting::remove_intermediate_files(job)
+            .context(SchedulerErrorKind::IntermediateFilesRemoveFailed)?;
+        Ok(())
+    }
+
+    /// Creates a recurring job, returning the ID assigned to it. A new job is scheduled from the
+    /// job template every time the cron expression fires.
+    pub fn create_recurring_job(&self, req: &RecurringJob) -> Result<String, SchedulerError> {
+        CronSchedule::parse(req.get_cron_expression())?;
+        validate_job(req.get_job_template())?;
+
+        let mut recurring_job = req.clone();
+        recurring_job.set_id(Uuid::new_v4().to_string());
+        recurring_job.set_paused(false);
+        recurring_job.set_time_created(Utc::now().timestamp() as u64);
+        recurring_job.clear_time_last_fired();
+        recurring_job.clear_last_job_id();
+
+        let _lock = self.recurring_lock.lock().unwrap();
+        self.save_recurring_job(&recurring_job)?;
+        info!(
+            "Recurring job {} created with schedule \"{}\".",
+            recurring_job.get_id(),
+            recurring_job.get_cron_expression()
+        );
+        Ok(recurring_job.get_id().to_string())
+    }
+
+    /// Returns all recurring jobs, including paused ones.
+    pub fn recurring_jobs(&self) -> Result<Vec<RecurringJob>, SchedulerError> {
+        Ok(self.store
+            .recurring_jobs()
+            .context(SchedulerErrorKind::StateAccessFailed)?)
+    }
+
+    /// Pauses or resumes a recurring job. A paused recurring job is not scheduled until it is
+    /// resumed, and firings missed while it was paused are skipped.
+    pub fn set_recurring_job_paused(
+        &self,
+        recurring_job_id: &str,
+        paused: bool,
+    ) -> Result<(), SchedulerError> {
+        let _lock = self.recurring_lock.lock().unwrap();
+        let mut recurring_job = self.get_recurring_job(recurring_job_id)?;
+        if !paused && recurring_job.get_paused() {
+            recurring_job.set_time_last_fired(Utc::now().timestamp() as u64);
+        }
+        recurring_job.set_paused(paused);
+        self.save_recurring_job(&recurring_job)?;
+        info!(
+            "Recurring job {} {}.",
+            recurring_job_id,
+            if paused { "paused" } else { "resumed" }
+        );
+        Ok(())
+    }
+
+    /// Deletes a recurring job. Jobs it has already scheduled keep running.
+    pub fn delete_recurring_job(&self, recurring_job_id: &str) -> Result<(), SchedulerError> {
+        let _lock = self.recurring_lock.lock().unwrap();
+        self.store
+            .remove_recurring_job(recurring_job_id)
+            .map_err(recurring_job_error)?;
+        info!("Recurring job {} deleted.", recurring_job_id);
+        Ok(())
+    }
+
+    /// Schedules the next stage of the pipeline of a job which is done. Returns the ID of the job
+    /// created for the next stage, or `None` if the job was the last stage.
+    pub fn schedule_next_stage(&self, job: &Job) -> Result<Option<String>, SchedulerError> {
+        match pipeline::next_stage(job)? {
+            Some(next) => {
+                info!(
+                    "Scheduling stage {} of pipeline {}.",
+                    next.get_stage_index(),
+                    next.get_pipeline_id()
+                );
+                self.schedule(&next).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Saves a job which has been split into tasks, along with its tasks, and marks it as in
+    /// progress. Returns a `JobCancelled` error if the job was cancelled while it was being split.
+    fn start_job(&self, job: &mut Job, plan: &splitting::TaskPlan) -> Result<(), SchedulerError> {
+        let _lock = self.status_lock.lock().unwrap();
+        match self.store.get_job(job.get_id()) {
+            Ok(ref saved) if is_finished(saved) => {
+                return Err(SchedulerErrorKind::JobCancelled.into());
+            }
+            // A job which doesn't depend on other jobs is first saved here.
+            Ok(_) => {}
+            Err(ref err) if err.kind() == StateErrorKind::JobNotFound => {}
+            Err(err) => return Err(err.context(SchedulerErrorKind::StateAccessFailed).into()),
+        }
+
+        job.set_status(JobStatus::JOB_PROCESSING);
+        self.save_job(job)?;
+        for task in plan.map_tasks.iter().chain(plan.reduce_tasks.iter()) {
+            self.store
+                .save_task(task)
+                .context(SchedulerErrorKind::StateAccessFailed)?;
+        }
+
+        job.set_status(JobStatus::JOB_IN_PROGRESS);
+        job.set_time_started(Utc::now().timestamp() as u64);
+        self.save_job(job)
+    }
+
+    /// Drives a job with its map tasks sent to the broker through to completion, and records
+    /// whether it succeeded. The usage of the job stops counting against its client's quota once
+    /// it has finished.
+    ///
+    /// This blocks until the job is done, so it should be run on its own thread.
+    fn run_job(
+        &self,
+        mut job: Job,
+        usage: Usage,
+        map_count: usize,
+        reduce_count: usize,
+        reduce_tasks: Vec<Task>,
+    ) {
+        let result = self.complete_job(&job, map_count, reduce_count, reduce_tasks);
+        self.queue.remove_job(job.get_id());
+        self.admission.release(job.get_client_id(), &usage);
+        match result {
+            Ok(()) => {
+                info!("Job {} done.", job.get_id());
+                job.set_status(JobStatus::JOB_DONE);
+            }
+            Err(ref err) if err.kind() == SchedulerErrorKind::JobCancelled => {
+                // The job was saved as cancelled by `cancel`.
+                return;
+            }
+            Err(err) => {
+                error!("Job {} failed: {}", job.get_id(), err);
+                job.set_status(JobStatus::JOB_FAILED);
+                job.set_failure_details(failure_details(&err));
+            }
+        }
+        job.set_time_done(Utc::now().timestamp() as u64);
+        match self.finish_job(&job) {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("Job {} was cancelled before it finished.", job.get_id());
+                return;
+            }
+            Err(err) => {
+                error!("Unable to save the status of job {}: {}", job.get_id(), err);
+                return;
+            }
+        }
+
+        if job.get_status() == JobStatus::JOB_FAILED {
+            // Tasks of the job which are still queued or running are dropped by the workers.
+            if let Err(err) = self.clean_up_job(&job) {
+                error!("Unable to clean up failed job {}: {}", job.get_id(), err);
+            }
+        } else if job.get_status() == JobStatus::JOB_DONE {
+            if let Err(err) = self.schedule_next_stage(&job) {
+                error!(
+                    "Unable to schedule the next stage of pipeline {}: {}",
+                    pipeline::pipeline_id(&job),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Sends the reduce tasks of a job once all of its map tasks are done, and waits for them to
+    /// be done in turn. `map_count` and `reduce_count` are the number of tasks in each phase,
+    /// whether or not they are still to be sent.
+    fn complete_job(
+        &self,
+        job: &Job,
+        map_count: usize,
+        reduce_count: usize,
+        reduce_tasks: Vec<Task>,
+    ) -> Result<(), SchedulerError> {
+        let policy = retry::retry_policy(job)?;
+        let timeout = retry::task_timeout()?;
+
+        self.wait_for_tasks(job, TaskKind::MAP, map_count, &policy, timeout)?;
+        info!(
+            "Map tasks of job {} done, sending reduce tasks.",
+            job.get_id()
+        );
+        self.send_tasks(job, reduce_tasks);
+
+        self.wait_for_tasks(job, TaskKind::REDUCE, reduce_count, &policy, timeout)
+    }
+
+    /// Waits until the state store reports that all tasks of the given kind in a job are done,
+    /// retrying failed tasks according to the retry policy of the job in the meantime. Tasks
+    /// running for longer than `timeout` seconds are failed, and duplicates of straggling tasks
+    /// are launched near the end of the phase.
+    ///
+    /// Cancelling a job removes its pending tasks, so the wait ends early for a cancelled job.
+    fn wait_for_tasks(
+        &self,
+        job: &Job,
+        kind: TaskKind,
+        total: usize,
+        policy: &RetryPolicy,
+        timeout: Option<u64>,
+    ) -> Result<(), SchedulerError> {
+        let speculation = speculation::SpeculationPolicy::from_settings()?;
+        let mut speculated = HashSet::new();
+        let done = self.phase_done(job, kind);
+        loop {
+            match done.try_recv() {
+                Ok(result) => {
+                    result.context(SchedulerErrorKind::StateAccessFailed)?;
+                    return self.check_cancelled(job);
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    return Err(SchedulerErrorKind::StateAccessFailed.into());
+                }
+            }
+
+            let pending = match kind {
+                TaskKind::MAP => self.store.pending_map_tasks(job),
+                TaskKind::REDUCE => self.store.pending_reduce_tasks(job),
+            }.context(SchedulerErrorKind::StateAccessFailed)?;
+            self.check_cancelled(job)?;
+            self.check_broker()?;
+
+            // Tasks which are done or waiting to be retried no longer count against the client.
+            let running: HashSet<String> = pending
+                .iter()
+                .filter(|task| task.get_status() != TaskStatus::TASK_FAILED)
+                .map(|task| task.get_id().to_string())
+                .collect();
+            self.queue.finish_tasks(job.get_id(), &running);
+
+            let now = Utc::now().timestamp() as u64;
+            for task in speculation.stragglers(&pending, total, &speculated, now) {
+                let duplicate = speculation::duplicate_attempt(task, self.next_attempt(task)?);
+                info!(
+                    "Launching attempt {} of straggling task {} of job {}.",
+                    duplicate.get_attempt(),
+                    task.get_id(),
+                    task.get_job_id()
+                );
+                speculated.insert(task.get_id().to_string());
+                self.send_tasks(job, vec![duplicate]);
+            }
+
+            for mut task in pending {
+                if let Some(timeout) = timeout {
+                    if retry::timed_out(&task, timeout, now) {
+                        warn!(
+                            "Task {} of job {} timed out after {} seconds.",
+                            task.get_id(),
+                            task.get_job_id(),
+                            timeout
+                        );
+                        retry::fail_timed_out(&mut task, timeout, now);
+                        self.store
+                            .save_task(&task)
+                            .context(SchedulerErrorKind::StateAccessFailed)?;
+                    }
+                }
+                if task.get_status() == TaskStatus::TASK_FAILED {
+                    self.retry_task(job, task, policy, now)?;
+                }
+            }
+            thread::sleep(Duration::from_secs(TASKS_POLL_INTERVAL_SECS));
+        }
+    }
+
+    /// Waits on its own thread for `map_done` or `reduce_done` of a job to resolve, and sends the
+    /// result on the returned channel. Failed jobs have their pending tasks cancelled, so the wait
+    /// always ends.
+    fn phase_done(&self, job: &Job, kind: TaskKind) -> Receiver<Result<(), StateError>> {
+        let done = match kind {
+            TaskKind::MAP => self.store.map_done(job),
+            TaskKind::REDUCE => self.store.reduce_done(job),
+        };
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            // The receiver is gone if the job stopped running first.
+            let _ = sender.send(done.wait());
+        });
+        receiver
+    }
+
+    /// Sends a failed task to the broker again once its backoff has passed. Fails if the task has
+    /// no attempts left.
+    fn retry_task(
+        &self,
+        job: &Job,
+        mut task: Task,
+        policy: &RetryPolicy,
+        now: u64,
+    ) -> Result<(), SchedulerError> {
+        if !retry::can_retry(policy, &task) {
+            let details = format!(
+                "Task {} failed after {} attempts: {}",
+                task.get_id(),
+                task.get_attempt(),
+                task.get_failure_details()
+            );
+            return Err(err_msg(details)
+                .context(SchedulerErrorKind::TaskAttemptsExhausted)
+                .into());
+        }
+        if !retry::ready_for_retry(policy, &task, now) {
+            return Ok(());
+        }
+
+        let next = self.next_attempt(&task)?;
+        retry::next_attempt(&mut task, next);
+        info!(
+            "Retrying task {} of job {}, attempt {} of {}.",
+            task.get_id(),
+            task.get_job_id(),
+            task.get_attempt(),
+            policy.get_max_attempts()
+        );
+        self.store
+            .save_task(&task)
+            .context(SchedulerErrorKind::StateAccessFailed)?;
+        self.send_tasks(job, vec![task]);
+        Ok(())
+    }
+
+    /// Schedules recurring jobs whenever they fire.
+    ///
+    /// This never returns, so it should be run on its own thread.
+    pub fn run_recurring_jobs(&self) {
+        loop {
+            if let Err(err) = self.schedule_due_recurring_jobs() {
+                error!("Unable to schedule recurring jobs: {}", err);
+            }
+            thread::sleep(Duration::from_secs(RECURRING_JOBS_POLL_INTERVAL_SECS));
+        }
+    }
+
+    /// Schedules a job for each recurring job which has fired since it was last scheduled.
+    ///
+    /// Scheduling a job splits its input, so the recurring jobs are not locked while it happens.
+    fn schedule_due_recurring_jobs(&self) -> Result<(), SchedulerError> {
+        let now = Utc::now().timestamp() as u64;
+        for recurring_job in self.fire_due_recurring_jobs(now)? {
+            // A job which couldn't be scheduled isn't retried until the next firing, so it
+            // doesn't fail over and over again.
+            match self.schedule(recurring_job.get_job_template()) {
+                Ok(job_id) => {
+                    info!(
+                        "Recurring job {} scheduled as job {}.",
+                        recurring_job.get_id(),
+                        job_id
+                    );
+                    self.set_last_job_id(recurring_job.get_id(), job_id)?;
+                }
+                Err(err) => error!(
+                    "Unable to schedule recurring job {}: {}",
+                    recurring_job.get_id(),
+                    failure_details(&err)
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the recurring jobs which have fired since they were last scheduled as fired at
+    /// `now`, and returns them.
+    fn fire_due_recurring_jobs(&self, now: u64) -> Result<Vec<RecurringJob>, SchedulerError> {
+        let _lock = self.recurring_lock.lock().unwrap();
+        let mut due = Vec::new();
+        for mut recurring_job in self.recurring_jobs()? {
+            let schedule = match CronSchedule::parse(recurring_job.get_cron_expression()) {
+                Ok(schedule) => schedule,
+                Err(err) => {
+                    error!("Recurring job {} is invalid: {}", recurring_job.get_id(), err);
+                    continue;
+                }
+            };
+            if !recurring::is_due(&recurring_job, &schedule, now) {
+                continue;
+            }
+            recurring_job.set_time_last_fired(now);
+            self.save_recurring_job(&recurring_job)?;
+            due.push(recurring_job);
+        }
+        Ok(due)
+    }
+
+    /// Records the job last scheduled for a recurring job, unless the recurring job has been
+    /// deleted since.
+    fn set_last_job_id(
+        &self,
+        recurring_job_id: &str,
+        job_id: String,
+    ) -> Result<(), SchedulerError> {
+        let _lock = self.recurring_lock.lock().unwrap();
+        let mut recurring_job = match self.get_recurring_job(recurring_job_id) {
+            Ok(recurring_job) => recurring_job,
+            Err(ref err) if err.kind() == SchedulerErrorKind::RecurringJobNotFound => {
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        recurring_job.set_last_job_id(job_id);
+        self.save_recurring_job(&recurring_job)
+    }
+
+    fn get_recurring_job(&self, recurring_job_id: &str) -> Result<RecurringJob, SchedulerError> {
+        Ok(self.store
+            .get_recurring_job(recurring_job_id)
+            .map_err(recurring_job_error)?)
+    }
+
+    fn save_recurring_job(&self, recurring_job: &RecurringJob) -> Result<(), SchedulerError> {
+        self.store
+            .save_recurring_job(recurring_job)
+            .context(SchedulerErrorKind::StateAccessFailed)?;
+        Ok(())
+    }
+
+    /// Returns a `JobCancelled` error if the job has been cancelled since it was scheduled.
+    fn check_cancelled(&self, job: &Job) -> Result<(), SchedulerError> {
+        let saved = self.store
+            .get_job(job.get_id())
+            .context(SchedulerErrorKind::StateAccessFailed)?;
+        if saved.get_status() == JobStatus::JOB_CANCELLED {
+            return Err(SchedulerErrorKind::JobCancelled.into());
+        }
+        Ok(())
+    }
+
+    /// Returns a `BrokerConnectionClosed` error if tasks can no longer be sent to the broker.
+    fn check_broker(&self) -> Result<(), SchedulerError> {
+        if self.broker_closed.load(Ordering::SeqCst) {
+            return Err(SchedulerErrorKind::BrokerConnectionClosed.into());
         }
+        Ok(())
+    }
+
+    /// Reserves the number of the next attempt at a task. Duplicate attempts of a task may still be
+    /// running when it is retried, so attempts are numbered past every attempt issued so far.
+    fn next_attempt(&self, task: &Task) -> Result<u32, SchedulerError> {
+        Ok(self.store
+            .next_attempt(task)
+            .context(SchedulerErrorKind::StateAccessFailed)?)
     }
 
-    pub fn schedule(&self, _job: &Job) -> Result<String, SchedulerError> {
-        unimplemented!()
+    fn save_job(&self, job: &Job) -> Result<(), SchedulerError> {
+        self.store
+            .save_job(job)
+            .context(SchedulerErrorKind::StateAccessFailed)?;
+        Ok(())
     }
 
-    pub fn cancel(&self, _job_id: &str) -> Result<(), SchedulerError> {
-        unimplemented!()
+    /// Queues tasks of a job to be sent to the broker.
+    fn send_tasks(&self, job: &Job, tasks: Vec<Task>) {
+        self.queue.push(job.get_client_id(), tasks);
+    }
+
+    /// Fails the running jobs and rejects new ones, as their tasks can no longer be sent to the
+    /// broker.
+    pub fn close_broker(&self) {
+        if !self.broker_closed.swap(true, Ordering::SeqCst) {
+            error!("The broker connection was closed, failing running jobs.");
+        }
+    }
+
+    /// Sends the tasks in the queue to the broker, most urgent first. Once the broker connection
+    /// is closed, the jobs waiting on their tasks fail instead of waiting forever.
+    fn dispatch(&self, mut handle: mpsc::Sender<Task>) {
+        loop {
+            let task = self.queue.pop();
+            handle = match handle.send(task).wait() {
+                Ok(handle) => handle,
+                Err(_) => {
+                    self.close_broker();
+                    return;
+                }
+            };
+        }
     }
 }
 
+/// Converts an error from loading or removing a recurring job into a scheduler error.
+fn recurring_job_error(err: StateError) -> SchedulerError {
+    match err.kind() {
+        StateErrorKind::RecurringJobNotFound => {
+            err.context(SchedulerErrorKind::RecurringJobNotFound).into()
+        }
+        StateErrorKind::InvalidRecurringJobId => {
+            err.context(SchedulerErrorKind::InvalidRecurringJobId).into()
+        }
+        _ => err.context(SchedulerErrorKind::StateAccessFailed).into(),
+    }
+}
+
+/// Describes an error along with everything that caused it.
+pub fn failure_details(err: &Fail) -> String {
+    let mut details = err.to_string();
+    let mut cause = err.cause();
+    while let Some(fail) = cause {
+        details.push_str(": ");
+        details.push_str(&fail.to_string());
+        cause = fail.cause();
+    }
+    details
+}
+
+/// Returns the tasks which no worker has started yet.
+fn unstarted(tasks: Vec<Task>) -> Vec<Task> {
+    tasks
+        .into_iter()
+        .filter(|task| task.get_status() == TaskStatus::TASK_PENDING)
+        .collect()
+}
+
+/// Returns whether a job has stopped running, whether or not it succeeded.
+fn is_finished(job: &Job) -> bool {
+    match job.get_status() {
+        JobStatus::JOB_DONE | JobStatus::JOB_FAILED | JobStatus::JOB_CANCELLED => true,
+        _ => false,
+    }
+}
+
+/// Checks that a job has everything needed to run it.
+fn validate_job(job: &Job) -> Result<(), SchedulerError> {
+    if job.get_payload_path().is_empty() {
+        return Err(SchedulerErrorKind::MissingPayload.into());
+    }
+    if job.get_input_directory().is_empty() && job.get_tagged_input_directories().is_empty() {
+        return Err(SchedulerErrorKind::MissingInput.into());
+    }
+    if job.get_input_kind() == InputDataKind::UNDEFINED {
+        return Err(SchedulerErrorKind::UndefinedInputKind.into());
+    }
+    if job.get_output_files().is_empty() {
+        return Err(SchedulerErrorKind::MissingOutputFiles.into());
+    }
+    let fraction = job.get_sample_fraction();
+    if !(fraction >= 0.0 && fraction <= 1.0) {
+        return Err(SchedulerErrorKind::InvalidSampleFraction.into());
+    }
+    Ok(())
+}
+
+/// Checks that the input directories of a job exist. This is done when the job is started rather
+/// than in `validate_job`, as the input of a job may be the output of a job it depends on.
+fn check_input_directories(job: &Job) -> Result<(), SchedulerError> {
+    let mut dirs: Vec<&str> = job.get_tagged_input_directories()
+        .iter()
+        .map(|dir| dir.get_path())
+        .collect();
+    if !job.get_input_directory().is_empty() {
+        dirs.push(job.get_input_directory());
+    }
+    for dir in dirs {
+        if !Path::new(dir).is_dir() {
+            return Err(err_msg(format!("Input directory \"{}\" does not exist.", dir))
+                .context(SchedulerErrorKind::InputDirectoryNotFound)
+                .into());
+        }
+    }
+    Ok(())
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
 pub enum SchedulerErrorKind {
     #[fail(display = "Undefined")]
     Undefined,
+    #[fail(display = "Failed to access the state store.")]
+    StateAccessFailed,
+    #[fail(display = "Output files of a pipeline stage must all be in the same directory.")]
+    InvalidPipelineStage,
+    #[fail(display = "Job has no payload.")]
+    MissingPayload,
+    #[fail(display = "Job has no input directory.")]
+    MissingInput,
+    #[fail(display = "Job has an UNDEFINED input kind.")]
+    UndefinedInputKind,
+    #[fail(display = "Job has no output files.")]
+    MissingOutputFiles,
+    #[fail(display = "Sample fraction must be between 0 and 1.")]
+    InvalidSampleFraction,
+    #[fail(display = "An input directory of the job does not exist.")]
+    InputDirectoryNotFound,
+    #[fail(display = "Failed to split the job into tasks.")]
+    SplittingFailed,
+    #[fail(display = "No job with the given ID exists.")]
+    JobNotFound,
+    #[fail(display = "No task with the given ID exists.")]
+    TaskNotFound,
+    #[fail(display = "Job has already finished.")]
+    JobAlreadyFinished,
+    #[fail(display = "Job was cancelled.")]
+    JobCancelled,
+    #[fail(display = "Failed to remove the intermediate files of the job.")]
+    IntermediateFilesRemoveFailed,
+    #[fail(display = "Failed to read the scheduler settings.")]
+    SettingsReadFailed,
+    #[fail(display = "A task failed too many times.")]
+    TaskAttemptsExhausted,
+    #[fail(display = "Client has exceeded its quota.")]
+    QuotaExceeded,
+    #[fail(display = "A job this job depends on does not exist.")]
+    DependencyNotFound,
+    #[fail(display = "A job this job depends on did not succeed.")]
+    DependencyFailed,
+    #[fail(display = "Invalid cron expression.")]
+    InvalidCronExpression,
+    #[fail(display = "No recurring job with the given ID exists.")]
+    RecurringJobNotFound,
+    #[fail(display = "Recurring job ID is not a valid UUID.")]
+    InvalidRecurringJobId,
+    #[fail(display = "The connection to the broker was closed.")]
+    BrokerConnectionClosed,
 }
 
 #[derive(Debug)]
@@ -72,3 +1083,408 @@ impl From<Context<SchedulerErrorKind>> for SchedulerError {
         SchedulerError { inner }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use futures::Stream;
+
+    use super::*;
+    use settings;
+    use settings::SETTINGS;
+    use state::FileStore;
+
+    fn valid_job() -> Job {
+        let mut job = Job::new();
+        job.set_payload_path("/payloads/wordcount".to_string());
+        job.set_input_directory("/input".to_string());
+        job.set_input_kind(InputDataKind::DATA_TEXT_NEWLINES);
+        job.mut_output_files().push("/output/0".to_string());
+        job
+    }
+
+    /// Creates a scheduler with the default settings, backed by a file store in a new directory.
+    fn test_scheduler() -> (Scheduler, Arc<FileStore>, PathBuf) {
+        settings::set_defaults(&mut SETTINGS.write().unwrap()).unwrap();
+        let state_dir = env::temp_dir().join(format!("heracles-state-{}", Uuid::new_v4()));
+        let store = Arc::new(FileStore::new(&state_dir).unwrap());
+        let (sender, receiver) = mpsc::channel(1);
+        let scheduler = Scheduler::new(sender, Arc::clone(&store)).unwrap();
+        // There is no broker in the tests, so sent tasks are discarded.
+        thread::spawn(move || receiver.for_each(|_| Ok(())).wait());
+        (scheduler, store, state_dir)
+    }
+
+    #[test]
+    fn test_schedule_with_default_settings() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        let mut input_dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        input_dir.push("testdata");
+        input_dir.push("text_with_newlines");
+        let mut job = valid_job();
+        job.set_input_directory(input_dir.to_str().unwrap().to_string());
+
+        let job_id = scheduler.schedule(&job).unwrap();
+
+        let saved = store.get_job(&job_id).unwrap();
+        assert_eq!(JobStatus::JOB_IN_PROGRESS, saved.get_status());
+        assert!(!store.pending_map_tasks(&saved).unwrap().is_empty());
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_schedule_next_stage() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        let mut output_dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        output_dir.push("testdata");
+        output_dir.push("text_with_newlines");
+        let mut next = valid_job();
+        next.clear_input_directory();
+        let mut job = valid_job();
+        job.set_id("first-job".to_string());
+        job.set_status(JobStatus::JOB_DONE);
+        job.mut_output_files()[0] = output_dir.join("0").to_str().unwrap().to_string();
+        job.mut_stages().push(next);
+
+        let next_id = scheduler.schedule_next_stage(&job).unwrap().unwrap();
+
+        let saved = store.get_job(&next_id).unwrap();
+        assert_eq!("first-job", saved.get_pipeline_id());
+        assert_eq!(1, saved.get_stage_index());
+        assert_eq!(JobStatus::JOB_IN_PROGRESS, saved.get_status());
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        // A job whose file was left half written.
+        fs::create_dir_all(state_dir.join("jobs").join("broken")).unwrap();
+        fs::write(state_dir.join("jobs").join("broken").join("request"), b"\x0a\xff").unwrap();
+        // A pipeline stage which finished without its next stage being scheduled.
+        let mut output_dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        output_dir.push("testdata");
+        output_dir.push("text_with_newlines");
+        let mut next = valid_job();
+        next.clear_input_directory();
+        let mut job = valid_job();
+        job.set_id("first-job".to_string());
+        job.set_status(JobStatus::JOB_DONE);
+        job.mut_output_files()[0] = output_dir.join("0").to_str().unwrap().to_string();
+        job.mut_stages().push(next);
+        store.save_job(&job).unwrap();
+
+        scheduler.recover().unwrap();
+
+        let next_stages: Vec<Job> = store
+            .job_ids()
+            .unwrap()
+            .iter()
+            .filter_map(|job_id| store.get_job(job_id).ok())
+            .filter(|job| job.get_pipeline_id() == "first-job")
+            .collect();
+        assert_eq!(1, next_stages.len());
+        assert_eq!(1, next_stages[0].get_stage_index());
+        assert_eq!(3, store.job_ids().unwrap().len());
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_tasks() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        let mut job = valid_job();
+        job.set_id("job".to_string());
+        store.save_job(&job).unwrap();
+        for task_id in &["map-0", "map-1"] {
+            let mut task = Task::new();
+            task.set_id(task_id.to_string());
+            task.set_job_id(job.get_id().to_string());
+            task.set_kind(TaskKind::MAP);
+            store.save_task(&task).unwrap();
+        }
+
+        assert_eq!(2, scheduler.get_tasks("job", "").unwrap().len());
+        let tasks = scheduler.get_tasks("job", "map-1").unwrap();
+        assert_eq!(1, tasks.len());
+        assert_eq!("map-1", tasks[0].get_id());
+        assert_eq!(
+            SchedulerErrorKind::TaskNotFound,
+            scheduler.get_tasks("job", "map-2").unwrap_err().kind()
+        );
+        assert_eq!(
+            SchedulerErrorKind::JobNotFound,
+            scheduler.get_tasks("missing", "").unwrap_err().kind()
+        );
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_schedule_with_closed_broker() {
+        settings::set_defaults(&mut SETTINGS.write().unwrap()).unwrap();
+        let state_dir = env::temp_dir().join(format!("heracles-state-{}", Uuid::new_v4()));
+        let store = Arc::new(FileStore::new(&state_dir).unwrap());
+        let (sender, receiver) = mpsc::channel(1);
+        drop(receiver);
+        let scheduler = Scheduler::new(sender, Arc::clone(&store)).unwrap();
+        let mut input_dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        input_dir.push("testdata");
+        input_dir.push("text_with_newlines");
+        let mut job = valid_job();
+        job.set_input_directory(input_dir.to_str().unwrap().to_string());
+
+        // The job's tasks can't be sent, so it fails rather than waiting for them.
+        let job_id = scheduler.schedule(&job).unwrap();
+        let mut status = store.get_job(&job_id).unwrap().get_status();
+        for _ in 0..100 {
+            if status != JobStatus::JOB_IN_PROGRESS {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+            status = store.get_job(&job_id).unwrap().get_status();
+        }
+        assert_eq!(JobStatus::JOB_FAILED, status);
+
+        let err = scheduler.schedule(&job).unwrap_err();
+        assert_eq!(SchedulerErrorKind::BrokerConnectionClosed, err.kind());
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_start_cancelled_job() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        let mut job = valid_job();
+        job.set_id("job".to_string());
+        job.set_status(JobStatus::JOB_CANCELLED);
+        store.save_job(&job).unwrap();
+        let plan = splitting::TaskPlan {
+            map_tasks: Vec::new(),
+            reduce_tasks: Vec::new(),
+        };
+
+        // The job was cancelled while it was being split.
+        job.set_status(JobStatus::JOB_WAITING);
+        let err = scheduler.start_job(&mut job, &plan).unwrap_err();
+        assert_eq!(SchedulerErrorKind::JobCancelled, err.kind());
+        assert_eq!(
+            JobStatus::JOB_CANCELLED,
+            store.get_job("job").unwrap().get_status()
+        );
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_finish_cancelled_job() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        let mut job = valid_job();
+        job.set_id("job".to_string());
+        job.set_status(JobStatus::JOB_CANCELLED);
+        store.save_job(&job).unwrap();
+
+        job.set_status(JobStatus::JOB_DONE);
+        assert!(!scheduler.finish_job(&job).unwrap());
+        assert_eq!(
+            JobStatus::JOB_CANCELLED,
+            store.get_job("job").unwrap().get_status()
+        );
+
+        let mut running = valid_job();
+        running.set_id("running".to_string());
+        running.set_status(JobStatus::JOB_IN_PROGRESS);
+        store.save_job(&running).unwrap();
+
+        running.set_status(JobStatus::JOB_FAILED);
+        assert!(scheduler.finish_job(&running).unwrap());
+        assert_eq!(
+            JobStatus::JOB_FAILED,
+            store.get_job("running").unwrap().get_status()
+        );
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_retry_task_with_running_duplicate() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        let mut job = valid_job();
+        job.set_id("job".to_string());
+        store.save_job(&job).unwrap();
+        let mut task = Task::new();
+        task.set_id("map-0".to_string());
+        task.set_job_id(job.get_id().to_string());
+        task.set_attempt(1);
+        task.set_status(TaskStatus::TASK_IN_PROGRESS);
+        store.save_task(&task).unwrap();
+
+        let attempt = scheduler.next_attempt(&task).unwrap();
+        let duplicate = speculation::duplicate_attempt(&task, attempt);
+        assert_eq!(2, duplicate.get_attempt());
+
+        // The original attempt fails while the duplicate is still running.
+        task.set_status(TaskStatus::TASK_FAILED);
+        store.save_task(&task).unwrap();
+        let mut policy = RetryPolicy::new();
+        policy.set_max_attempts(5);
+        scheduler.retry_task(&job, task, &policy, 0).unwrap();
+
+        let retried = store.pending_map_tasks(&job).unwrap().remove(0);
+        assert_eq!(3, retried.get_attempt());
+        assert_eq!(TaskStatus::TASK_PENDING, retried.get_status());
+        assert_eq!(1, retried.get_previous_attempts()[0].get_attempt());
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_resume() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        let mut job = valid_job();
+        job.set_id("job".to_string());
+        job.set_status(JobStatus::JOB_IN_PROGRESS);
+        store.save_job(&job).unwrap();
+        for (task_id, kind) in vec![("map-0", TaskKind::MAP), ("reduce-0", TaskKind::REDUCE)] {
+            let mut task = Task::new();
+            task.set_id(task_id.to_string());
+            task.set_job_id(job.get_id().to_string());
+            task.set_kind(kind);
+            task.set_attempt(1);
+            task.set_status(TaskStatus::TASK_PENDING);
+            store.save_task(&task).unwrap();
+        }
+
+        scheduler.resume(job.clone()).unwrap();
+
+        let map_task = store.pending_map_tasks(&job).unwrap().remove(0);
+        assert_eq!(2, map_task.get_attempt());
+        assert_eq!(TaskStatus::TASK_PENDING, map_task.get_status());
+        assert_eq!(1, map_task.get_previous_attempts()[0].get_attempt());
+        // The reduce task is not sent until the map task is done.
+        let reduce_task = store.pending_reduce_tasks(&job).unwrap().remove(0);
+        assert_eq!(1, reduce_task.get_attempt());
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_fire_due_recurring_jobs() {
+        let (scheduler, store, state_dir) = test_scheduler();
+        let now = Utc::now().timestamp() as u64;
+        let mut recurring_job = RecurringJob::new();
+        recurring_job.set_id(Uuid::new_v4().to_string());
+        recurring_job.set_cron_expression("* * * * *".to_string());
+        recurring_job.set_time_created(now - 120);
+        store.save_recurring_job(&recurring_job).unwrap();
+
+        let due = scheduler.fire_due_recurring_jobs(now).unwrap();
+        assert_eq!(1, due.len());
+        let saved = store.get_recurring_job(recurring_job.get_id()).unwrap();
+        assert_eq!(now, saved.get_time_last_fired());
+        assert!(scheduler.fire_due_recurring_jobs(now).unwrap().is_empty());
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_recurring_job_id() {
+        let (scheduler, _store, state_dir) = test_scheduler();
+
+        let err = scheduler
+            .delete_recurring_job("../jobs/job/request")
+            .unwrap_err();
+        assert_eq!(SchedulerErrorKind::InvalidRecurringJobId, err.kind());
+        let err = scheduler
+            .set_recurring_job_paused("../jobs/job/request", true)
+            .unwrap_err();
+        assert_eq!(SchedulerErrorKind::InvalidRecurringJobId, err.kind());
+        fs::remove_dir_all(state_dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_job() {
+        assert!(validate_job(&valid_job()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_job_without_input() {
+        let mut job = valid_job();
+        job.clear_input_directory();
+
+        let err = validate_job(&job).unwrap_err();
+        assert_eq!(SchedulerErrorKind::MissingInput, err.kind());
+
+        let mut tagged = TaggedInputDirectory::new();
+        tagged.set_path("/input".to_string());
+        tagged.set_tag("first".to_string());
+        job.mut_tagged_input_directories().push(tagged);
+        assert!(validate_job(&job).is_ok());
+    }
+
+    #[test]
+    fn test_validate_job_with_invalid_sample_fraction() {
+        let mut job = valid_job();
+        job.set_sample_fraction(0.5);
+        assert!(validate_job(&job).is_ok());
+
+        for fraction in &[-0.1, 1.5, ::std::f64::NAN] {
+            job.set_sample_fraction(*fraction);
+            let err = validate_job(&job).unwrap_err();
+            assert_eq!(SchedulerErrorKind::InvalidSampleFraction, err.kind());
+        }
+    }
+
+    #[test]
+    fn test_check_input_directories() {
+        let mut job = valid_job();
+        let mut input_dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        input_dir.push("testdata");
+        job.set_input_directory(input_dir.to_str().unwrap().to_string());
+        assert!(check_input_directories(&job).is_ok());
+
+        let mut tagged = TaggedInputDirectory::new();
+        tagged.set_path(input_dir.join("missing").to_str().unwrap().to_string());
+        job.mut_tagged_input_directories().push(tagged);
+        let err = check_input_directories(&job).unwrap_err();
+        assert_eq!(SchedulerErrorKind::InputDirectoryNotFound, err.kind());
+    }
+
+    #[test]
+    fn test_is_finished() {
+        let mut job = valid_job();
+        job.set_status(JobStatus::JOB_IN_PROGRESS);
+        assert!(!is_finished(&job));
+
+        job.set_status(JobStatus::JOB_CANCELLED);
+        assert!(is_finished(&job));
+    }
+
+    #[test]
+    fn test_unstarted() {
+        let tasks: Vec<Task> = [
+            TaskStatus::TASK_PENDING,
+            TaskStatus::TASK_IN_PROGRESS,
+            TaskStatus::TASK_FAILED,
+            TaskStatus::TASK_PENDING,
+        ].iter()
+            .enumerate()
+            .map(|(index, status)| {
+                let mut task = Task::new();
+                task.set_id(index.to_string());
+                task.set_status(*status);
+                task
+            })
+            .collect();
+
+        let ids: Vec<String> = unstarted(tasks)
+            .iter()
+            .map(|task| task.get_id().to_string())
+            .collect();
+        assert_eq!(vec!["0", "3"], ids);
+    }
+
+    #[test]
+    fn test_validate_job_without_output_files() {
+        let mut job = valid_job();
+        job.clear_output_files();
+
+        let err = validate_job(&job).unwrap_err();
+        assert_eq!(SchedulerErrorKind::MissingOutputFiles, err.kind());
+    }
+}
@@ -0,0 +1,107 @@
+//! Module for chaining jobs into multi-stage pipelines.
+//!
+//! A pipeline is a job with a list of `stages` attached. When the job is done, the first stage is
+//! turned into a new job reading the output of the job, and the remaining stages are attached to
+//! it in turn.
+
+use std::path::Path;
+
+use protobuf::RepeatedField;
+
+use super::*;
+
+/// Creates the job for the stage of the pipeline following `job`, or returns `None` if `job` is
+/// the last stage.
+pub fn next_stage(job: &Job) -> Result<Option<Job>, SchedulerError> {
+    let mut stages = job.get_stages().to_vec();
+    if stages.is_empty() {
+        return Ok(None);
+    }
+    let mut next = stages.remove(0);
+
+    next.set_input_directory(output_directory(job)?);
+    next.clear_tagged_input_directories();
+    next.set_stages(RepeatedField::from_vec(stages));
+    next.set_pipeline_id(pipeline_id(job).to_string());
+    next.set_stage_index(job.get_stage_index() + 1);
+    if next.get_client_id().is_empty() {
+        next.set_client_id(job.get_client_id().to_string());
+    }
+    Ok(Some(next))
+}
+
+/// Returns the ID of the pipeline the job belongs to. The first job of a pipeline has no
+/// `pipeline_id` set, so its own ID is used.
+pub fn pipeline_id(job: &Job) -> &str {
+    if job.get_pipeline_id().is_empty() {
+        job.get_id()
+    } else {
+        job.get_pipeline_id()
+    }
+}
+
+/// Returns the directory holding all output files of the job.
+fn output_directory(job: &Job) -> Result<String, SchedulerError> {
+    let mut dirs = job.get_output_files()
+        .iter()
+        .map(|file| Path::new(file).parent().map(|dir| dir.to_string_lossy().to_string()));
+    let first = match dirs.next() {
+        Some(Some(dir)) => dir,
+        _ => return Err(SchedulerErrorKind::InvalidPipelineStage.into()),
+    };
+    if dirs.any(|dir| dir.as_ref() != Some(&first)) {
+        return Err(SchedulerErrorKind::InvalidPipelineStage.into());
+    }
+    Ok(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline_job() -> Job {
+        let mut second = Job::new();
+        second.set_payload_path("/payloads/second".to_string());
+        let mut third = Job::new();
+        third.set_payload_path("/payloads/third".to_string());
+
+        let mut job = Job::new();
+        job.set_id("first-job".to_string());
+        job.set_client_id("client".to_string());
+        job.set_output_files(RepeatedField::from_vec(vec![
+            "/output/first/0".to_string(),
+            "/output/first/1".to_string(),
+        ]));
+        job.set_stages(RepeatedField::from_vec(vec![second, third]));
+        job
+    }
+
+    #[test]
+    fn test_next_stage() {
+        let next = next_stage(&pipeline_job()).unwrap().unwrap();
+
+        assert_eq!("/payloads/second", next.get_payload_path());
+        assert_eq!("/output/first", next.get_input_directory());
+        assert_eq!("first-job", next.get_pipeline_id());
+        assert_eq!(1, next.get_stage_index());
+        assert_eq!("client", next.get_client_id());
+        assert_eq!(1, next.get_stages().len());
+        assert_eq!("/payloads/third", next.get_stages()[0].get_payload_path());
+    }
+
+    #[test]
+    fn test_last_stage() {
+        let mut job = pipeline_job();
+        job.clear_stages();
+
+        assert!(next_stage(&job).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_output_files_in_different_directories() {
+        let mut job = pipeline_job();
+        job.mut_output_files().push("/elsewhere/2".to_string());
+
+        assert!(next_stage(&job).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! Module for the queue of tasks waiting to be sent to the broker.
+//!
+//! Tasks are sent to the broker in order of priority, so that tasks of urgent jobs overtake the
+//! tasks already waiting. Clients whose next tasks are of the same priority take turns according
+//! to the `FairShare` policy, and tasks of the same client and priority are sent in the order they
+//! were queued.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::{Condvar, Mutex};
+
+use super::fairshare::FairShare;
+use super::*;
+
+/// `TaskQueue` holds tasks waiting to be sent to the broker, and keeps track of the tasks in
+/// flight for each client.
+pub struct TaskQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+    policy: FairShare,
+}
+
+#[derive(Default)]
+struct QueueState {
+    clients: HashMap<String, ClientQueue>,
+    /// Tasks sent to the broker which have not finished yet, by `attempt_key`.
+    in_flight: HashMap<String, InFlightTask>,
+    /// Number of tasks queued so far, used to keep tasks of the same priority in order.
+    queued_count: u64,
+    /// Number of tasks sent so far, used to find the client whose turn it is.
+    sent_count: u64,
+}
+
+#[derive(Default)]
+struct ClientQueue {
+    tasks: BinaryHeap<QueuedTask>,
+    in_flight: usize,
+    /// Value of `sent_count` when a task of the client was last sent.
+    last_sent: u64,
+}
+
+struct InFlightTask {
+    client_id: String,
+    job_id: String,
+    task_id: String,
+}
+
+struct QueuedTask {
+    task: Task,
+    sequence: u64,
+}
+
+impl Ord for QueuedTask {
+    fn cmp(&self, other: &QueuedTask) -> Ordering {
+        self.task
+            .get_priority()
+            .cmp(&other.task.get_priority())
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for QueuedTask {
+    fn partial_cmp(&self, other: &QueuedTask) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedTask {
+    fn eq(&self, other: &QueuedTask) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedTask {}
+
+/// Identifies an attempt at a task. Duplicate attempts of a task can be in flight at once.
+fn attempt_key(task: &Task) -> String {
+    format!("{}/{}", task.get_id(), task.get_attempt())
+}
+
+impl QueueState {
+    /// Takes the next task to send, if any client with queued tasks is below its limit.
+    fn next_task(&mut self, policy: &FairShare) -> Option<Task> {
+        let client_id = {
+            let mut eligible: Vec<(&String, &ClientQueue)> = self.clients
+                .iter()
+                .filter(|&(client_id, queue)| {
+                    !queue.tasks.is_empty() && policy.below_limit(client_id, queue.in_flight)
+                })
+                .collect();
+            let priority = eligible
+                .iter()
+                .filter_map(|&(_, queue)| queue.tasks.peek())
+                .map(|queued| queued.task.get_priority())
+                .max()?;
+            eligible.retain(|&(_, queue)| {
+                queue.tasks.peek().map(|queued| queued.task.get_priority()) == Some(priority)
+            });
+
+            // The client with the fewest tasks in flight for its weight goes next, and clients
+            // which are equally far below their share take turns.
+            eligible
+                .into_iter()
+                .min_by(|&(a_id, a), &(b_id, b)| {
+                    let a_share = a.in_flight as u64 * u64::from(policy.weight(b_id));
+                    let b_share = b.in_flight as u64 * u64::from(policy.weight(a_id));
+                    a_share
+                        .cmp(&b_share)
+                        .then_with(|| a.last_sent.cmp(&b.last_sent))
+                        .then_with(|| a_id.cmp(b_id))
+                })
+                .map(|(client_id, _)| client_id.clone())?
+        };
+
+        self.sent_count += 1;
+        let sent_count = self.sent_count;
+        let queue = self.clients.get_mut(&client_id)?;
+        let task = queue.tasks.pop()?.task;
+        queue.in_flight += 1;
+        queue.last_sent = sent_count;
+        self.in_flight.insert(
+            attempt_key(&task),
+            InFlightTask {
+                client_id,
+                job_id: task.get_job_id().to_string(),
+                task_id: task.get_id().to_string(),
+            },
+        );
+        Some(task)
+    }
+
+    /// Stops counting the given in flight tasks against their clients.
+    fn release<F>(&mut self, mut should_release: F)
+    where
+        F: FnMut(&InFlightTask) -> bool,
+    {
+        let released: Vec<String> = self.in_flight
+            .iter()
+            .filter(|&(_, task)| should_release(task))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in released {
+            if let Some(task) = self.in_flight.remove(&key) {
+                if let Some(queue) = self.clients.get_mut(&task.client_id) {
+                    queue.in_flight -= 1;
+                }
+            }
+        }
+        self.clients
+            .retain(|_, queue| !queue.tasks.is_empty() || queue.in_flight > 0);
+    }
+}
+
+impl TaskQueue {
+    pub fn new(policy: FairShare) -> Self {
+        TaskQueue {
+            state: Mutex::new(Default::default()),
+            available: Condvar::new(),
+            policy,
+        }
+    }
+
+    /// Adds tasks submitted by a client to the queue.
+    pub fn push(&self, client_id: &str, tasks: Vec<Task>) {
+        let mut state = self.state.lock().unwrap();
+        for task in tasks {
+            let sequence = state.queued_count;
+            state.queued_count += 1;
+            state
+                .clients
+                .entry(client_id.to_string())
+                .or_insert_with(Default::default)
+                .tasks
+                .push(QueuedTask { task, sequence });
+        }
+        self.available.notify_all();
+    }
+
+    /// Removes the next task to send from the queue and counts it as in flight, waiting until
+    /// there is a task which can be sent.
+    pub fn pop(&self) -> Task {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(task) = state.next_task(&self.policy) {
+                return task;
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+
+    /// Marks the tasks of a job which were sent to the broker as finished, except for the ones in
+    /// `running`.
+    pub fn finish_tasks(&self, job_id: &str, running: &HashSet<String>) {
+        let mut state = self.state.lock().unwrap();
+        state.release(|task| task.job_id == job_id && !running.contains(&task.task_id));
+        self.available.notify_all();
+    }
+
+    /// Removes all tasks of a job from the queue, and marks the ones in flight as finished.
+    pub fn remove_job(&self, job_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        for queue in state.clients.values_mut() {
+            let tasks: Vec<QueuedTask> = queue
+                .tasks
+                .drain()
+                .filter(|queued| queued.task.get_job_id() != job_id)
+                .collect();
+            queue.tasks = BinaryHeap::from(tasks);
+        }
+        state.release(|task| task.job_id == job_id);
+        self.available.notify_all();
+    }
+
+    /// Returns the number of tasks in the queue.
+    pub fn len(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.clients.values().map(|queue| queue.tasks.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, job_id: &str, priority: u64) -> Task {
+        let mut task = Task::new();
+        task.set_id(id.to_string());
+        task.set_job_id(job_id.to_string());
+        task.set_attempt(1);
+        task.set_priority(priority);
+        task
+    }
+
+    fn try_pop(queue: &TaskQueue) -> Option<Task> {
+        queue.state.lock().unwrap().next_task(&queue.policy)
+    }
+
+    #[test]
+    fn test_pop_by_priority() {
+        let queue = TaskQueue::new(FairShare::default());
+        queue.push("client", vec![task("low", "job", 1), task("high", "job", 5)]);
+        queue.push("client", vec![task("medium", "job", 3)]);
+
+        assert_eq!("high", queue.pop().get_id());
+        assert_eq!("medium", queue.pop().get_id());
+        assert_eq!("low", queue.pop().get_id());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_same_priority_in_queued_order() {
+        let queue = TaskQueue::new(FairShare::default());
+        queue.push("client", vec![task("first", "job", 2), task("second", "job", 2)]);
+        queue.push("client", vec![task("third", "job", 2)]);
+
+        assert_eq!("first", queue.pop().get_id());
+        assert_eq!("second", queue.pop().get_id());
+        assert_eq!("third", queue.pop().get_id());
+    }
+
+    #[test]
+    fn test_clients_take_turns() {
+        let queue = TaskQueue::new(FairShare::default());
+        queue.push("a", vec![task("a1", "job_a", 0), task("a2", "job_a", 0)]);
+        queue.push("b", vec![task("b1", "job_b", 0), task("b2", "job_b", 0)]);
+
+        let order: Vec<String> = (0..4).map(|_| queue.pop().get_id().to_string()).collect();
+        assert_eq!(vec!["a1", "b1", "a2", "b2"], order);
+    }
+
+    #[test]
+    fn test_in_flight_limit() {
+        let policy = FairShare {
+            max_in_flight: 1,
+            ..Default::default()
+        };
+        let queue = TaskQueue::new(policy);
+        queue.push("a", vec![task("a1", "job_a", 0), task("a2", "job_a", 0)]);
+
+        assert_eq!("a1", try_pop(&queue).unwrap().get_id());
+        assert!(try_pop(&queue).is_none());
+
+        queue.finish_tasks("job_a", &HashSet::new());
+        assert_eq!("a2", try_pop(&queue).unwrap().get_id());
+    }
+
+    #[test]
+    fn test_weights() {
+        let mut policy = FairShare {
+            max_in_flight: 1,
+            ..Default::default()
+        };
+        policy.weights.insert("heavy".to_string(), 2);
+        let queue = TaskQueue::new(policy);
+        queue.push(
+            "heavy",
+            vec![task("h1", "job_h", 0), task("h2", "job_h", 0), task("h3", "job_h", 0)],
+        );
+        queue.push("light", vec![task("l1", "job_l", 0), task("l2", "job_l", 0)]);
+
+        let mut sent = Vec::new();
+        while let Some(task) = try_pop(&queue) {
+            sent.push(task.get_id().to_string());
+        }
+        assert_eq!(vec!["h1", "l1", "h2"], sent);
+    }
+
+    #[test]
+    fn test_remove_job() {
+        let queue = TaskQueue::new(FairShare::default());
+        queue.push("a", vec![task("a1", "job_a", 0), task("a2", "job_a", 0)]);
+        queue.push("b", vec![task("b1", "job_b", 0)]);
+        assert_eq!("a1", queue.pop().get_id());
+
+        queue.remove_job("job_a");
+        assert_eq!(1, queue.len());
+        assert_eq!("b1", queue.pop().get_id());
+        assert!(queue.state.lock().unwrap().in_flight.values().all(|task| task.job_id == "job_b"));
+    }
+}
@@ -0,0 +1,62 @@
+//! Module for jobs which are scheduled repeatedly, following a cron schedule.
+//!
+//! Recurring jobs are kept in the state store. The scheduler checks them periodically, and
+//! schedules a new job from the template of each recurring job which has fired since it was last
+//! scheduled. A recurring job which fired several times while the manager was down is only
+//! scheduled once.
+
+use std::cmp;
+
+use super::cron::CronSchedule;
+use super::*;
+
+/// Returns whether a recurring job should be scheduled at `now`.
+pub fn is_due(recurring_job: &RecurringJob, schedule: &CronSchedule, now: u64) -> bool {
+    if recurring_job.get_paused() {
+        return false;
+    }
+    let last_fired = cmp::max(
+        recurring_job.get_time_last_fired(),
+        recurring_job.get_time_created(),
+    );
+    schedule
+        .next_after(last_fired)
+        .map_or(false, |next| next <= now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 14th of March 2018, 10:00 UTC.
+    const CREATED: u64 = 1_521_021_600;
+
+    fn hourly() -> (RecurringJob, CronSchedule) {
+        let mut recurring_job = RecurringJob::new();
+        recurring_job.set_cron_expression("0 * * * *".to_string());
+        recurring_job.set_time_created(CREATED);
+        let schedule = CronSchedule::parse(recurring_job.get_cron_expression()).unwrap();
+        (recurring_job, schedule)
+    }
+
+    #[test]
+    fn test_is_due() {
+        let (mut recurring_job, schedule) = hourly();
+
+        assert!(!is_due(&recurring_job, &schedule, CREATED + 30 * 60));
+        assert!(is_due(&recurring_job, &schedule, CREATED + 60 * 60));
+
+        recurring_job.set_time_last_fired(CREATED + 60 * 60);
+        assert!(!is_due(&recurring_job, &schedule, CREATED + 90 * 60));
+        // Firings missed while the manager was down only schedule the job once.
+        assert!(is_due(&recurring_job, &schedule, CREATED + 5 * 60 * 60));
+    }
+
+    #[test]
+    fn test_paused() {
+        let (mut recurring_job, schedule) = hourly();
+        recurring_job.set_paused(true);
+
+        assert!(!is_due(&recurring_job, &schedule, CREATED + 60 * 60));
+    }
+}
@@ -0,0 +1,208 @@
+//! Module for retrying failed tasks.
+//!
+//! A failed task is retried until it has been attempted `max_attempts` times. Before each retry
+//! the scheduler waits for a backoff which doubles with each failed attempt.
+//!
+//! A task which has been running for longer than the task timeout is assumed to have been lost
+//! along with its worker, and is failed so it can be retried.
+
+use std::cmp;
+
+use settings::SETTINGS;
+
+use super::*;
+
+/// Returns the retry policy of a job, with the manager's defaults filled in for unset fields.
+pub fn retry_policy(job: &Job) -> Result<RetryPolicy, SchedulerError> {
+    let settings = SETTINGS.read().unwrap();
+    let mut defaults = RetryPolicy::new();
+    defaults.set_max_attempts(
+        settings
+            .get::<u32>("retry.max_attempts")
+            .context(SchedulerErrorKind::SettingsReadFailed)?,
+    );
+    defaults.set_initial_backoff_secs(
+        settings
+            .get::<u64>("retry.initial_backoff_secs")
+            .context(SchedulerErrorKind::SettingsReadFailed)?,
+    );
+    defaults.set_max_backoff_secs(
+        settings
+            .get::<u64>("retry.max_backoff_secs")
+            .context(SchedulerErrorKind::SettingsReadFailed)?,
+    );
+    Ok(with_defaults(job.get_retry_policy(), &defaults))
+}
+
+/// Fills in the unset fields of a retry policy from `defaults`.
+fn with_defaults(policy: &RetryPolicy, defaults: &RetryPolicy) -> RetryPolicy {
+    let mut ret = policy.clone();
+    if ret.get_max_attempts() == 0 {
+        ret.set_max_attempts(defaults.get_max_attempts());
+    }
+    if ret.get_initial_backoff_secs() == 0 {
+        ret.set_initial_backoff_secs(defaults.get_initial_backoff_secs());
+    }
+    if ret.get_max_backoff_secs() == 0 {
+        ret.set_max_backoff_secs(defaults.get_max_backoff_secs());
+    }
+    ret
+}
+
+/// Returns the number of the current attempt at running a task.
+fn attempt(task: &Task) -> u32 {
+    cmp::max(task.get_attempt(), 1)
+}
+
+/// Returns how long to wait after the given attempt failed before starting the next one.
+pub fn backoff_secs(policy: &RetryPolicy, attempt: u32) -> u64 {
+    let mut backoff = policy.get_initial_backoff_secs();
+    for _ in 1..attempt {
+        if backoff >= policy.get_max_backoff_secs() {
+            break;
+        }
+        backoff = backoff.saturating_mul(2);
+    }
+    cmp::min(backoff, policy.get_max_backoff_secs())
+}
+
+/// Returns whether a failed task has any attempts left.
+pub fn can_retry(policy: &RetryPolicy, task: &Task) -> bool {
+    attempt(task) < policy.get_max_attempts()
+}
+
+/// Returns whether the backoff after the last failed attempt of a task has passed.
+pub fn ready_for_retry(policy: &RetryPolicy, task: &Task, now: u64) -> bool {
+    task.get_time_done() + backoff_secs(policy, attempt(task)) <= now
+}
+
+/// Returns the task timeout in seconds, or `None` if tasks never time out.
+pub fn task_timeout() -> Result<Option<u64>, SchedulerError> {
+    let timeout: u64 = SETTINGS
+        .read()
+        .unwrap()
+        .get("task_timeout")
+        .context(SchedulerErrorKind::SettingsReadFailed)?;
+    Ok(if timeout == 0 { None } else { Some(timeout) })
+}
+
+/// Returns whether a task has been running for longer than `timeout` seconds.
+pub fn timed_out(task: &Task, timeout: u64, now: u64) -> bool {
+    task.get_status() == TaskStatus::TASK_IN_PROGRESS && task.get_time_started() + timeout < now
+}
+
+/// Marks a task which timed out as failed.
+pub fn fail_timed_out(task: &mut Task, timeout: u64, now: u64) {
+    task.set_status(TaskStatus::TASK_FAILED);
+    task.set_time_done(now);
+    task.set_failure_details(format!(
+        "Task did not finish within {} seconds, its worker may have been lost.",
+        timeout
+    ));
+}
+
+/// Records the failed attempt in the task, and resets the task so it can be attempted again as
+/// the given attempt.
+pub fn next_attempt(task: &mut Task, next: u32) {
+    let mut previous = TaskAttempt::new();
+    previous.set_attempt(attempt(task));
+    previous.set_worker_id(task.take_worker_id());
+    previous.set_time_started(task.get_time_started());
+    previous.set_time_done(task.get_time_done());
+    previous.set_failure_details(task.take_failure_details());
+    task.mut_previous_attempts().push(previous);
+
+    task.set_attempt(next);
+    task.set_status(TaskStatus::TASK_PENDING);
+    task.clear_time_started();
+    task.clear_time_done();
+    task.clear_progress();
+    task.clear_metrics();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        let mut policy = RetryPolicy::new();
+        policy.set_max_attempts(3);
+        policy.set_initial_backoff_secs(5);
+        policy.set_max_backoff_secs(30);
+        policy
+    }
+
+    fn failed_task() -> Task {
+        let mut task = Task::new();
+        task.set_attempt(1);
+        task.set_status(TaskStatus::TASK_FAILED);
+        task.set_worker_id("worker".to_string());
+        task.set_time_started(100);
+        task.set_time_done(110);
+        task.set_failure_details("payload crashed".to_string());
+        task
+    }
+
+    #[test]
+    fn test_with_defaults() {
+        let mut job_policy = RetryPolicy::new();
+        job_policy.set_max_attempts(5);
+
+        let merged = with_defaults(&job_policy, &policy());
+        assert_eq!(5, merged.get_max_attempts());
+        assert_eq!(5, merged.get_initial_backoff_secs());
+        assert_eq!(30, merged.get_max_backoff_secs());
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let policy = policy();
+        assert_eq!(5, backoff_secs(&policy, 1));
+        assert_eq!(10, backoff_secs(&policy, 2));
+        assert_eq!(20, backoff_secs(&policy, 3));
+        assert_eq!(30, backoff_secs(&policy, 4));
+        assert_eq!(30, backoff_secs(&policy, 100));
+    }
+
+    #[test]
+    fn test_ready_for_retry() {
+        let task = failed_task();
+        assert!(!ready_for_retry(&policy(), &task, 114));
+        assert!(ready_for_retry(&policy(), &task, 115));
+    }
+
+    #[test]
+    fn test_timed_out() {
+        let mut task = Task::new();
+        task.set_status(TaskStatus::TASK_IN_PROGRESS);
+        task.set_time_started(100);
+
+        assert!(!timed_out(&task, 60, 160));
+        assert!(timed_out(&task, 60, 161));
+
+        fail_timed_out(&mut task, 60, 161);
+        assert_eq!(TaskStatus::TASK_FAILED, task.get_status());
+        assert_eq!(161, task.get_time_done());
+        assert!(!timed_out(&task, 60, 1000));
+    }
+
+    #[test]
+    fn test_next_attempt() {
+        let mut task = failed_task();
+        next_attempt(&mut task, 2);
+
+        assert_eq!(2, task.get_attempt());
+        assert_eq!(TaskStatus::TASK_PENDING, task.get_status());
+        assert_eq!(0, task.get_time_done());
+        assert!(task.get_worker_id().is_empty());
+        assert_eq!(1, task.get_previous_attempts().len());
+        let previous = &task.get_previous_attempts()[0];
+        assert_eq!(1, previous.get_attempt());
+        assert_eq!("worker", previous.get_worker_id());
+        assert_eq!("payload crashed", previous.get_failure_details());
+
+        assert!(can_retry(&policy(), &task));
+        next_attempt(&mut task, 3);
+        assert!(!can_retry(&policy(), &task));
+    }
+}
@@ -0,0 +1,152 @@
+//! Module for speculative execution of straggler tasks.
+//!
+//! Near the end of a phase, the few tasks still running hold up the whole job if their workers are
+//! slow. Once enough of the tasks of a phase are done, a duplicate attempt of each task which has
+//! been running for a while is sent to the broker. Whichever attempt finishes first is kept, and
+//! the worker running the other attempt discards its results.
+
+use std::collections::HashSet;
+
+use settings::SETTINGS;
+
+use super::*;
+
+/// `SpeculationPolicy` decides when duplicate attempts of running tasks are launched.
+#[derive(Clone, Copy, Debug)]
+pub struct SpeculationPolicy {
+    /// Fraction of the tasks of a phase which must be done before duplicates are launched. 0
+    /// disables speculative execution.
+    pub threshold: f64,
+    /// How long a task must have been running for before a duplicate of it is launched.
+    pub min_runtime_secs: u64,
+}
+
+impl SpeculationPolicy {
+    pub fn from_settings() -> Result<Self, SchedulerError> {
+        let settings = SETTINGS.read().unwrap();
+        Ok(SpeculationPolicy {
+            threshold: settings
+                .get("speculation.threshold")
+                .context(SchedulerErrorKind::SettingsReadFailed)?,
+            min_runtime_secs: settings
+                .get("speculation.min_runtime_secs")
+                .context(SchedulerErrorKind::SettingsReadFailed)?,
+        })
+    }
+
+    /// Picks the running tasks of a phase to launch duplicate attempts of, slowest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The tasks of the phase which are not done yet.
+    /// * `total` - The number of tasks in the phase.
+    /// * `speculated` - IDs of the tasks which already have a duplicate attempt.
+    /// * `now` - The current UNIX timestamp.
+    pub fn stragglers<'a>(
+        &self,
+        pending: &'a [Task],
+        total: usize,
+        speculated: &HashSet<String>,
+        now: u64,
+    ) -> Vec<&'a Task> {
+        if self.threshold <= 0.0 || total == 0 {
+            return Vec::new();
+        }
+        let done = total.saturating_sub(pending.len());
+        if (done as f64) < self.threshold * total as f64 {
+            return Vec::new();
+        }
+
+        let mut stragglers: Vec<&Task> = pending
+            .iter()
+            .filter(|task| {
+                task.get_status() == TaskStatus::TASK_IN_PROGRESS
+                    && !speculated.contains(task.get_id())
+                    && task.get_time_started() + self.min_runtime_secs <= now
+            })
+            .collect();
+        stragglers.sort_by_key(|task| task.get_time_started());
+        stragglers
+    }
+}
+
+/// Creates a duplicate of a running task, as the given attempt.
+pub fn duplicate_attempt(task: &Task, attempt: u32) -> Task {
+    let mut duplicate = task.clone();
+    duplicate.set_attempt(attempt);
+    duplicate.set_speculative(true);
+    duplicate.set_status(TaskStatus::TASK_PENDING);
+    duplicate.clear_worker_id();
+    duplicate.clear_time_started();
+    duplicate.clear_time_done();
+    duplicate.clear_progress();
+    duplicate.clear_metrics();
+    duplicate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> SpeculationPolicy {
+        SpeculationPolicy {
+            threshold: 0.75,
+            min_runtime_secs: 60,
+        }
+    }
+
+    fn running_task(id: &str, time_started: u64) -> Task {
+        let mut task = Task::new();
+        task.set_id(id.to_string());
+        task.set_attempt(1);
+        task.set_status(TaskStatus::TASK_IN_PROGRESS);
+        task.set_time_started(time_started);
+        task
+    }
+
+    #[test]
+    fn test_no_stragglers_before_threshold() {
+        let pending = vec![running_task("a", 0), running_task("b", 0)];
+
+        let stragglers = policy().stragglers(&pending, 4, &HashSet::new(), 1000);
+        assert!(stragglers.is_empty());
+    }
+
+    #[test]
+    fn test_stragglers_slowest_first() {
+        let pending = vec![
+            running_task("a", 500),
+            running_task("b", 100),
+            running_task("c", 990),
+        ];
+
+        let stragglers = policy().stragglers(&pending, 12, &HashSet::new(), 1000);
+        let ids: Vec<&str> = stragglers.iter().map(|task| task.get_id()).collect();
+        assert_eq!(vec!["b", "a"], ids);
+    }
+
+    #[test]
+    fn test_stragglers_skips_speculated_tasks() {
+        let pending = vec![running_task("a", 0), running_task("b", 0)];
+        let mut speculated = HashSet::new();
+        speculated.insert("a".to_string());
+
+        let stragglers = policy().stragglers(&pending, 8, &speculated, 1000);
+        assert_eq!(1, stragglers.len());
+        assert_eq!("b", stragglers[0].get_id());
+    }
+
+    #[test]
+    fn test_duplicate_attempt() {
+        let mut task = running_task("a", 100);
+        task.set_worker_id("worker".to_string());
+
+        let duplicate = duplicate_attempt(&task, 2);
+        assert_eq!("a", duplicate.get_id());
+        assert_eq!(2, duplicate.get_attempt());
+        assert!(duplicate.get_speculative());
+        assert_eq!(TaskStatus::TASK_PENDING, duplicate.get_status());
+        assert!(duplicate.get_worker_id().is_empty());
+        assert_eq!(0, duplicate.get_time_started());
+    }
+}
@@ -1,9 +1,10 @@
-use grpc::{RequestOptions, SingleResponse};
+use grpc::{GrpcMessageError, GrpcStatus, RequestOptions, SingleResponse};
+use protobuf::RepeatedField;
 
 use super::*;
 use heracles_proto::mapreduce as pb;
 use heracles_proto::mapreduce_grpc as grpc_pb;
-use scheduler::Scheduler;
+use scheduler::{failure_details, Scheduler, SchedulerError, SchedulerErrorKind};
 
 pub struct JobScheduleService {
     scheduler: Scheduler,
@@ -13,6 +14,46 @@ impl JobScheduleService {
     pub fn new(scheduler: Scheduler) -> Self {
         JobScheduleService { scheduler }
     }
+
+    fn describe_job(&self, job_id: &str) -> SingleResponse<pb::Description> {
+        if job_id.is_empty() {
+            return SingleResponse::err(grpc_message_error(
+                GrpcStatus::Argument,
+                "A job ID must be given to describe a job".to_string(),
+            ));
+        }
+        match self.scheduler.get_job(job_id) {
+            Ok(job) => {
+                let mut res = pb::Description::new();
+                res.mut_jobs().push(job);
+                SingleResponse::completed(res)
+            }
+            Err(err) => {
+                error!("{}", err);
+                SingleResponse::err(grpc_error(&err))
+            }
+        }
+    }
+
+    fn describe_tasks(&self, job_id: &str, task_id: &str) -> SingleResponse<pb::Description> {
+        if job_id.is_empty() {
+            return SingleResponse::err(grpc_message_error(
+                GrpcStatus::Argument,
+                "A job ID must be given to describe tasks".to_string(),
+            ));
+        }
+        match self.scheduler.get_tasks(job_id, task_id) {
+            Ok(tasks) => {
+                let mut res = pb::Description::new();
+                res.set_tasks(RepeatedField::from_vec(tasks));
+                SingleResponse::completed(res)
+            }
+            Err(err) => {
+                error!("{}", err);
+                SingleResponse::err(grpc_error(&err))
+            }
+        }
+    }
 }
 
 impl grpc_pb::JobScheduleService for JobScheduleService {
@@ -29,7 +70,7 @@ impl grpc_pb::JobScheduleService for JobScheduleService {
             }
             Err(err) => {
                 error!("{}", err);
-                return SingleResponse::err(grpc::Error::Other(""));
+                return SingleResponse::err(grpc_error(&err));
             }
         }
     }
@@ -45,7 +86,7 @@ impl grpc_pb::JobScheduleService for JobScheduleService {
             }
             Err(err) => {
                 error!("{}", err);
-                return SingleResponse::err(grpc::Error::Other(""));
+                return SingleResponse::err(grpc_error(&err));
             }
         }
     }
@@ -55,6 +96,161 @@ impl grpc_pb::JobScheduleService for JobScheduleService {
         _: RequestOptions,
         req: pb::DescribeRequest,
     ) -> SingleResponse<pb::Description> {
-        unimplemented!()
+        match req.get_resource() {
+            pb::ResourceType::JOB => self.describe_job(req.get_job_id()),
+            pb::ResourceType::TASK => self.describe_tasks(req.get_job_id(), req.get_task_id()),
+            resource => SingleResponse::err(grpc_message_error(
+                GrpcStatus::Unimplemented,
+                format!("Describing {:?} resources is not supported yet", resource),
+            )),
+        }
+    }
+
+    fn create_recurring_job(
+        &self,
+        _: RequestOptions,
+        req: pb::CreateRecurringJobRequest,
+    ) -> SingleResponse<pb::CreateRecurringJobResponse> {
+        match self.scheduler.create_recurring_job(req.get_recurring_job()) {
+            Ok(recurring_job_id) => {
+                let mut res = pb::CreateRecurringJobResponse::new();
+                res.set_recurring_job_id(recurring_job_id);
+                SingleResponse::completed(res)
+            }
+            Err(err) => {
+                error!("{}", err);
+                SingleResponse::err(grpc_error(&err))
+            }
+        }
+    }
+
+    fn list_recurring_jobs(
+        &self,
+        _: RequestOptions,
+        _: pb::EmptyMessage,
+    ) -> SingleResponse<pb::ListRecurringJobsResponse> {
+        match self.scheduler.recurring_jobs() {
+            Ok(recurring_jobs) => {
+                let mut res = pb::ListRecurringJobsResponse::new();
+                res.set_recurring_jobs(RepeatedField::from_vec(recurring_jobs));
+                SingleResponse::completed(res)
+            }
+            Err(err) => {
+                error!("{}", err);
+                SingleResponse::err(grpc_error(&err))
+            }
+        }
+    }
+
+    fn pause_recurring_job(
+        &self,
+        _: RequestOptions,
+        req: pb::RecurringJobRequest,
+    ) -> SingleResponse<pb::EmptyMessage> {
+        empty_response(
+            self.scheduler
+                .set_recurring_job_paused(req.get_recurring_job_id(), true),
+        )
+    }
+
+    fn resume_recurring_job(
+        &self,
+        _: RequestOptions,
+        req: pb::RecurringJobRequest,
+    ) -> SingleResponse<pb::EmptyMessage> {
+        empty_response(
+            self.scheduler
+                .set_recurring_job_paused(req.get_recurring_job_id(), false),
+        )
+    }
+
+    fn delete_recurring_job(
+        &self,
+        _: RequestOptions,
+        req: pb::RecurringJobRequest,
+    ) -> SingleResponse<pb::EmptyMessage> {
+        empty_response(
+            self.scheduler
+                .delete_recurring_job(req.get_recurring_job_id()),
+        )
+    }
+}
+
+/// Responds with an empty message if the request succeeded.
+fn empty_response(result: Result<(), SchedulerError>) -> SingleResponse<pb::EmptyMessage> {
+    match result {
+        Ok(()) => SingleResponse::completed(pb::EmptyMessage::new()),
+        Err(err) => {
+            error!("{}", err);
+            SingleResponse::err(grpc_error(&err))
+        }
+    }
+}
+
+/// Converts a scheduler error into a gRPC error with the status code matching its kind, so clients
+/// can tell why their request failed.
+fn grpc_error(err: &SchedulerError) -> grpc::Error {
+    grpc_message_error(grpc_status(err.kind()), failure_details(err))
+}
+
+fn grpc_message_error(status: GrpcStatus, message: String) -> grpc::Error {
+    grpc::Error::GrpcMessage(GrpcMessageError {
+        grpc_status: status as i32,
+        grpc_message: message,
+    })
+}
+
+fn grpc_status(kind: SchedulerErrorKind) -> GrpcStatus {
+    match kind {
+        SchedulerErrorKind::MissingPayload
+        | SchedulerErrorKind::MissingInput
+        | SchedulerErrorKind::UndefinedInputKind
+        | SchedulerErrorKind::MissingOutputFiles
+        | SchedulerErrorKind::InvalidSampleFraction
+        | SchedulerErrorKind::InvalidPipelineStage
+        | SchedulerErrorKind::DependencyNotFound
+        | SchedulerErrorKind::InvalidCronExpression
+        | SchedulerErrorKind::InvalidRecurringJobId => GrpcStatus::Argument,
+        SchedulerErrorKind::QuotaExceeded => GrpcStatus::ResourceExhausted,
+        SchedulerErrorKind::JobNotFound
+        | SchedulerErrorKind::InputDirectoryNotFound
+        | SchedulerErrorKind::TaskNotFound
+        | SchedulerErrorKind::RecurringJobNotFound => GrpcStatus::NotFound,
+        SchedulerErrorKind::JobAlreadyFinished => GrpcStatus::FailedPrecondition,
+        SchedulerErrorKind::BrokerConnectionClosed => GrpcStatus::Unavailable,
+        _ => GrpcStatus::Internal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grpc_status() {
+        assert_eq!(
+            GrpcStatus::ResourceExhausted as i32,
+            grpc_status(SchedulerErrorKind::QuotaExceeded) as i32
+        );
+        assert_eq!(
+            GrpcStatus::Argument as i32,
+            grpc_status(SchedulerErrorKind::MissingPayload) as i32
+        );
+        assert_eq!(
+            GrpcStatus::Internal as i32,
+            grpc_status(SchedulerErrorKind::StateAccessFailed) as i32
+        );
+        assert_eq!(
+            GrpcStatus::Internal as i32,
+            grpc_status(SchedulerErrorKind::SplittingFailed) as i32
+        );
+        assert_eq!(
+            GrpcStatus::NotFound as i32,
+            grpc_status(SchedulerErrorKind::InputDirectoryNotFound) as i32
+        );
+        assert_eq!(
+            GrpcStatus::Unavailable as i32,
+            grpc_status(SchedulerErrorKind::BrokerConnectionClosed) as i32
+        );
     }
 }
@@ -27,6 +27,7 @@ pub fn init<'a>(opts: &ArgMatches<'a>) -> Result<(), Error> {
     // We read command line options after reading the config file so that the command line has
     // priority.
     set_options(&mut settings, opts)?;
+    validate(&settings)?;
 
     debug!(
         "{:?}",
@@ -59,14 +60,55 @@ fn set_options<'a>(settings: &mut Config, opts: &ArgMatches<'a>) -> Result<(), E
     if let Some(value) = opts.value_of("broker.queue_name") {
         settings.set("broker.queue_name", value)?;
     }
+    if let Some(value) = opts.value_of("intermediate_directory") {
+        settings.set("intermediate_directory", value)?;
+    }
+    if let Some(value) = opts.value_of("task_timeout") {
+        let v = value
+            .parse::<u64>()
+            .context(SettingsErrorKind::OptionParseFailed)?;
+        // Timeouts too large for the i64 `Config` stores integers as are rejected by `validate`.
+        settings.set("task_timeout", v as i64)?;
+    }
+    if let Some(value) = opts.value_of("state.location") {
+        settings.set("state.location", value)?;
+    }
     Ok(())
 }
 
-fn set_defaults(settings: &mut Config) -> Result<(), Error> {
-    settings.set_default("broker.queue_name", "heracles_tasks")?;
+/// Checks the settings which can't be checked when they are read. `Config` stores integers as
+/// i64, and reading a negative value as a u64 wraps it around rather than failing.
+fn validate(settings: &Config) -> Result<(), Error> {
+    let task_timeout: i64 = settings
+        .get("task_timeout")
+        .context(SettingsErrorKind::InvalidTaskTimeout)?;
+    if task_timeout < 0 {
+        return Err(SettingsError::from(SettingsErrorKind::InvalidTaskTimeout).into());
+    }
+    Ok(())
+}
+
+/// Sets the default value of every setting.
+pub fn set_defaults(settings: &mut Config) -> Result<(), Error> {
+    settings.set_default("broker.max_priority", 10)?;
+    // Queues can't be redeclared with different arguments, so the priority queue has a different name
+    // from the `heracles_tasks` queue used before tasks had priorities.
+    settings.set_default("broker.queue_name", "heracles_priority_tasks")?;
+    settings.set_default("fair_share.max_in_flight", 64)?;
     settings.set_default("input_chunk_size", 67_108_864_i64)?; // 64 MiB
+    settings.set_default("intermediate_directory", "/tmp/heracles/intermediate")?;
+    settings.set_default("quota.max_input_bytes", 0)?;
+    settings.set_default("quota.max_jobs", 0)?;
+    settings.set_default("quota.max_tasks", 0)?;
+    settings.set_default("retry.initial_backoff_secs", 5)?;
+    settings.set_default("retry.max_attempts", 3)?;
+    settings.set_default("retry.max_backoff_secs", 300)?;
     settings.set_default("server.port", 8081)?;
     settings.set_default("server.thread_pool_size", 8)?;
+    settings.set_default("speculation.min_runtime_secs", 60)?;
+    settings.set_default("speculation.threshold", 0.9)?;
+    settings.set_default("state.location", "/tmp/heracles/state")?;
+    settings.set_default("task_timeout", 3600)?; // 1 hour
     Ok(())
 }
 
@@ -74,6 +116,8 @@ fn set_defaults(settings: &mut Config) -> Result<(), Error> {
 pub enum SettingsErrorKind {
     #[fail(display = "Failed to parse command line option.")]
     OptionParseFailed,
+    #[fail(display = "Task timeout must be a whole number of seconds, at least 0.")]
+    InvalidTaskTimeout,
 }
 
 #[derive(Debug)]
@@ -116,3 +160,21 @@ impl From<Context<SettingsErrorKind>> for SettingsError {
         SettingsError { inner }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_task_timeout() {
+        let mut settings = Config::default();
+        set_defaults(&mut settings).unwrap();
+        assert!(validate(&settings).is_ok());
+
+        settings.set("task_timeout", -1).unwrap();
+        assert!(validate(&settings).is_err());
+
+        settings.set("task_timeout", "an hour").unwrap();
+        assert!(validate(&settings).is_err());
+    }
+}
@@ -10,6 +10,9 @@ use heracles_proto::datatypes::*;
 pub fn split(job: &Job) -> Result<Vec<Task>, Error> {
     match job.get_input_kind() {
         InputDataKind::UNDEFINED => Err(SplitterErrorKind::InvalidInputDataKind.into()),
-        InputDataKind::DATA_TEXT_NEWLINES => text::LineSplitter::split(job),
+        InputDataKind::DATA_TEXT_NEWLINES
+        | InputDataKind::DATA_CSV
+        | InputDataKind::DATA_TSV
+        | InputDataKind::DATA_JSON_LINES => text::LineSplitter::split(job),
     }
 }
@@ -2,9 +2,11 @@
 
 use std::fs::{read_dir, DirEntry, File};
 use std::io;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
 use std::path::Path;
 
+use cerberus::chunking::split_lines;
+use cerberus::manifest::MANIFEST_EXTENSION;
 use chrono::Utc;
 use failure::*;
 use rayon::prelude::*;
@@ -20,77 +22,214 @@ impl LineSplitter {
     /// Splits a job into a set of tasks.
     pub fn split(job: &Job) -> Result<Vec<Task>, Error> {
         let mut ret = Vec::new();
-        let entries: Vec<io::Result<DirEntry>> = read_dir(job.get_input_directory())
-            .context(SplitterErrorKind::InputDirectoryOpenFailed)?
-            .collect();
+        let mut entries: Vec<(io::Result<DirEntry>, String)> = Vec::new();
+        for (dir, tag) in LineSplitter::input_directories(job) {
+            entries.extend(
+                read_dir(dir)
+                    .context(SplitterErrorKind::InputDirectoryOpenFailed)?
+                    .map(|entry| (entry, tag.clone())),
+            );
+        }
+        let input_chunk_size: u64 = SETTINGS.read().unwrap().get("input_chunk_size")?;
+        let has_header = match job.get_input_kind() {
+            InputDataKind::DATA_CSV | InputDataKind::DATA_TSV => true,
+            _ => false,
+        };
         // Maps each directory entry to a vector of input chunks, then flattens them all into a
         // single vector of chunks.
         let input_chunks: Vec<InputChunk> = entries
             .into_par_iter()
-            .map(|entry| {
+            .map(|(entry, tag)| {
                 let path = entry.context(SplitterErrorKind::GenericIOError)?.path();
-                LineSplitter::split_file(path)
+                // Skip the manifests written next to the output of a previous pipeline stage.
+                if path.extension().map_or(false, |ext| ext == MANIFEST_EXTENSION) {
+                    return Ok(Vec::new());
+                }
+                LineSplitter::split_file(path, &tag, has_header, input_chunk_size)
             })
             .collect::<Result<Vec<Vec<InputChunk>>, Error>>()?
             .into_iter()
             .flat_map(|v| v)
             .collect();
+        let input_chunks = LineSplitter::sample_chunks(
+            input_chunks,
+            job.get_sample_fraction(),
+            job.get_sample_seed(),
+        )?;
+        let input_chunks =
+            LineSplitter::limit_records(input_chunks, job.get_sample_record_limit())?;
         for input in input_chunks {
             let mut task = Task::new();
             task.set_id(Uuid::new_v4().to_string());
             task.set_job_id(job.get_id().to_string());
             task.set_status(TaskStatus::TASK_PENDING);
+            task.set_attempt(1);
+            task.set_priority(job.get_priority());
             task.set_kind(TaskKind::MAP);
             task.set_time_created(Utc::now().timestamp() as u64);
             task.set_input_chunk(input);
+            task.set_input_kind(job.get_input_kind());
             task.set_payload_path(job.get_payload_path().to_string());
             ret.push(task);
         }
         Ok(ret)
     }
 
-    /// Splits a single input file into a set of chunks. Each map task gets one chunk.
-    fn split_file<P: AsRef<Path> + Clone>(p: P) -> Result<Vec<InputChunk>, Error> {
+    /// Returns the `(directory, tag)` pairs to read input from. The untagged `input_directory` is
+    /// included if it is set, with an empty tag.
+    fn input_directories(job: &Job) -> Vec<(String, String)> {
         let mut ret = Vec::new();
-        let f = File::open(p.clone()).context(SplitterErrorKind::FileOpenFailed)?;
-        let reader = BufReader::new(f);
-        let mut amount_read_this_chunk: u64 = 0;
-        let mut chunk_start_index: u64 = 0;
-        let task_input_size: u64 = SETTINGS.read().unwrap().get("task_input_size")?;
-
-        for line in reader.lines() {
-            let line = line.context(SplitterErrorKind::FileReadFailed)?;
-            // Currently this means that the function only supports text files with UNIX-style line
-            // endings. Although the chunking does not need to be so accurate that a single
-            // character will make much of a difference.
-            let len_with_newline_char = line.len() + 1;
-            if amount_read_this_chunk + len_with_newline_char as u64 > task_input_size {
-                // Check for the special case where the current chunk is empty and the next line
-                // would put it over capacity. In that case we have no choice but to take the line
-                // whole.
-                if amount_read_this_chunk == 0 {
-                    amount_read_this_chunk += len_with_newline_char as u64;
-                }
-                ret.push(LineSplitter::create_input_file(
-                    p.clone(),
-                    chunk_start_index,
-                    amount_read_this_chunk,
-                ));
-                amount_read_this_chunk = 0;
-                chunk_start_index += amount_read_this_chunk;
-            }
-            amount_read_this_chunk += len_with_newline_char as u64;
+        if !job.get_input_directory().is_empty() {
+            ret.push((job.get_input_directory().to_string(), String::new()));
+        }
+        for dir in job.get_tagged_input_directories() {
+            ret.push((dir.get_path().to_string(), dir.get_tag().to_string()));
+        }
+        ret
+    }
+
+    /// Chooses a deterministic subset of the chunks when the job only runs over a sample of its
+    /// input.
+    ///
+    /// Each chunk is ranked by a hash of its location and the seed, and the lowest ranked
+    /// `fraction` of the chunks are kept, in their original order. At least one chunk is always
+    /// kept.
+    fn sample_chunks(
+        chunks: Vec<InputChunk>,
+        fraction: f64,
+        seed: u64,
+    ) -> Result<Vec<InputChunk>, Error> {
+        if !(fraction >= 0.0 && fraction <= 1.0) {
+            return Err(SplitterErrorKind::InvalidSampleFraction.into());
+        }
+        if fraction == 0.0 || fraction == 1.0 || chunks.is_empty() {
+            return Ok(chunks);
         }
-        // Add the final chunk
-        ret.push(LineSplitter::create_input_file(
-            p,
-            chunk_start_index,
-            amount_read_this_chunk,
-        ));
 
+        let sample_size = ((chunks.len() as f64 * fraction).ceil() as usize).max(1);
+        let mut ranked: Vec<(u64, usize)> = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| (LineSplitter::chunk_hash(chunk, seed), index))
+            .collect();
+        ranked.sort();
+        let mut keep: Vec<usize> = ranked
+            .into_iter()
+            .take(sample_size)
+            .map(|(_, index)| index)
+            .collect();
+        keep.sort();
+
+        let mut chunks: Vec<Option<InputChunk>> = chunks.into_iter().map(Some).collect();
+        Ok(keep.into_iter()
+            .filter_map(|index| chunks[index].take())
+            .collect())
+    }
+
+    /// Truncates the chunks so they hold at most `limit` records between them, when the job only
+    /// runs over the first records of its input. A limit of 0 keeps every record.
+    ///
+    /// Chunks are taken in order of their path and offset, so the same records are kept whatever
+    /// order the input directories are listed in. The last chunk kept is cut short at a line
+    /// ending.
+    fn limit_records(mut chunks: Vec<InputChunk>, limit: u64) -> Result<Vec<InputChunk>, Error> {
+        if limit == 0 {
+            return Ok(chunks);
+        }
+        chunks.sort_by(|a, b| {
+            (a.get_path(), a.get_start_byte()).cmp(&(b.get_path(), b.get_start_byte()))
+        });
+
+        let mut ret = Vec::new();
+        let mut remaining = limit;
+        for mut chunk in chunks {
+            if remaining == 0 {
+                break;
+            }
+            let (records, end_byte) = LineSplitter::count_records(&chunk, remaining)?;
+            chunk.set_end_byte(end_byte);
+            remaining -= records;
+            ret.push(chunk);
+        }
         Ok(ret)
     }
 
+    /// Counts the records in a chunk, up to `max`. Returns the number of records counted and the
+    /// byte offset just past the last of them.
+    fn count_records(chunk: &InputChunk, max: u64) -> Result<(u64, u64), Error> {
+        let mut f = File::open(chunk.get_path()).context(SplitterErrorKind::FileOpenFailed)?;
+        f.seek(SeekFrom::Start(chunk.get_start_byte()))
+            .context(SplitterErrorKind::FileReadFailed)?;
+        let mut reader = BufReader::new(f).take(chunk.get_end_byte() - chunk.get_start_byte());
+
+        let mut records = 0;
+        let mut end_byte = chunk.get_start_byte();
+        let mut line = Vec::new();
+        while records < max {
+            line.clear();
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .context(SplitterErrorKind::FileReadFailed)?;
+            if read == 0 {
+                break;
+            }
+            records += 1;
+            end_byte += read as u64;
+        }
+        Ok((records, end_byte))
+    }
+
+    /// FNV-1a hash of a chunk's location and a seed. A fixed hash function is used rather than
+    /// `DefaultHasher`, so the same seed chooses the same sample across manager versions.
+    fn chunk_hash(chunk: &InputChunk, seed: u64) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        {
+            let mut add_byte = |byte: u8| {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            };
+            for shift in 0..8 {
+                add_byte((seed >> (shift * 8)) as u8);
+            }
+            for byte in chunk.get_path().bytes() {
+                add_byte(byte);
+            }
+            for shift in 0..8 {
+                add_byte((chunk.get_start_byte() >> (shift * 8)) as u8);
+            }
+        }
+        hash
+    }
+
+    /// Splits a single input file into chunks of at most `input_chunk_size` bytes, cut at line
+    /// endings. Each map task gets one chunk. The start and end of a chunk are both byte offsets
+    /// from the start of the file.
+    ///
+    /// If `has_header` is set, the first line of the file is kept out of the chunks and instead
+    /// attached to each of them as their header.
+    fn split_file<P: AsRef<Path> + Clone>(
+        p: P,
+        tag: &str,
+        has_header: bool,
+        input_chunk_size: u64,
+    ) -> Result<Vec<InputChunk>, Error> {
+        let f = File::open(p.clone()).context(SplitterErrorKind::FileOpenFailed)?;
+        let chunks = split_lines(BufReader::new(f), input_chunk_size, has_header)
+            .context(SplitterErrorKind::FileReadFailed)?;
+        let header = chunks.header;
+
+        Ok(chunks
+            .ranges
+            .into_iter()
+            .map(|(start, end)| {
+                let mut chunk = LineSplitter::create_input_file(p.clone(), start, end);
+                chunk.set_tag(tag.to_string());
+                chunk.set_header(header.clone());
+                chunk
+            })
+            .collect())
+    }
+
     /// Small helper function to create an `InputChunk` proto.
     fn create_input_file<P: AsRef<Path>>(path: P, start: u64, end: u64) -> InputChunk {
         let mut ret = InputChunk::new();
@@ -103,6 +242,7 @@ impl LineSplitter {
 
 #[cfg(test)]
 mod tests {
+    use std::io::Read;
     use std::path::PathBuf;
 
     use super::*;
@@ -115,7 +255,7 @@ mod tests {
         SETTINGS
             .write()
             .unwrap()
-            .set("task_input_size", 1024)
+            .set("input_chunk_size", 1024)
             .unwrap();
         let mut test_job = Job::new();
         test_job.set_input_directory(dir.to_str().unwrap().to_string());
@@ -123,7 +263,196 @@ mod tests {
         let tasks = LineSplitter::split(&test_job).unwrap();
 
         assert_eq!(2, tasks.len());
+        assert_eq!(0, tasks[0].get_input_chunk().get_start_byte());
         assert_eq!(1003, tasks[0].get_input_chunk().get_end_byte());
-        assert_eq!(838, tasks[1].get_input_chunk().get_end_byte());
+        assert_eq!(1003, tasks[1].get_input_chunk().get_start_byte());
+        assert_eq!(1841, tasks[1].get_input_chunk().get_end_byte());
+    }
+
+    #[test]
+    fn test_splitting_tagged_directories() {
+        let mut dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        dir.push("testdata");
+        dir.push("text_with_newlines");
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("input_chunk_size", 1024)
+            .unwrap();
+        let mut tagged_dir = TaggedInputDirectory::new();
+        tagged_dir.set_path(dir.to_str().unwrap().to_string());
+        tagged_dir.set_tag("users".to_string());
+        let mut test_job = Job::new();
+        test_job.mut_tagged_input_directories().push(tagged_dir);
+
+        let tasks = LineSplitter::split(&test_job).unwrap();
+
+        assert_eq!(2, tasks.len());
+        assert!(
+            tasks
+                .iter()
+                .all(|task| task.get_input_chunk().get_tag() == "users")
+        );
+    }
+
+    #[test]
+    fn test_splitting_file_with_header() {
+        let mut dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        dir.push("testdata");
+        dir.push("csv_with_header");
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("input_chunk_size", 1024)
+            .unwrap();
+        let mut test_job = Job::new();
+        test_job.set_input_directory(dir.to_str().unwrap().to_string());
+        test_job.set_input_kind(InputDataKind::DATA_CSV);
+
+        let tasks = LineSplitter::split(&test_job).unwrap();
+
+        assert_eq!(1, tasks.len());
+        assert_eq!(InputDataKind::DATA_CSV, tasks[0].get_input_kind());
+        assert_eq!("name,age", tasks[0].get_input_chunk().get_header());
+        assert_eq!(9, tasks[0].get_input_chunk().get_start_byte());
+        assert_eq!(25, tasks[0].get_input_chunk().get_end_byte());
+    }
+
+    /// Reads the bytes of a file covered by a chunk, the same way the worker does.
+    fn read_chunk(chunk: &InputChunk) -> String {
+        let mut data = Vec::new();
+        File::open(chunk.get_path())
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        let range = chunk.get_start_byte() as usize..chunk.get_end_byte() as usize;
+        String::from_utf8(data[range].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_splitting_file_with_header_into_chunks() {
+        let mut path: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        path.push("testdata");
+        path.push("csv_with_header");
+        path.push("people.csv");
+
+        let chunks = LineSplitter::split_file(path, "", true, 10).unwrap();
+
+        assert_eq!(2, chunks.len());
+        assert_eq!((9, 18), (chunks[0].get_start_byte(), chunks[0].get_end_byte()));
+        assert_eq!((18, 25), (chunks[1].get_start_byte(), chunks[1].get_end_byte()));
+        assert_eq!("alice,30\n", read_chunk(&chunks[0]));
+        assert_eq!("bob,25\n", read_chunk(&chunks[1]));
+        assert!(chunks.iter().all(|chunk| chunk.get_header() == "name,age"));
+    }
+
+    #[test]
+    fn test_chunks_cover_whole_file() {
+        let mut path: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        path.push("testdata");
+        path.push("text_with_newlines");
+        path.push("text_with_newlines.txt");
+        let mut data = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+
+        let chunks = LineSplitter::split_file(path, "", false, 100).unwrap();
+
+        assert!(chunks.len() > 1);
+        assert!(
+            chunks
+                .windows(2)
+                .all(|w| w[0].get_end_byte() == w[1].get_start_byte())
+        );
+        let joined: String = chunks.iter().map(read_chunk).collect();
+        assert_eq!(data, joined);
+    }
+
+    fn chunks_for_sampling(count: u64) -> Vec<InputChunk> {
+        (0..count)
+            .map(|i| LineSplitter::create_input_file("input", i * 100, (i + 1) * 100))
+            .collect()
+    }
+
+    #[test]
+    fn test_sampling_chunks() {
+        let sample = LineSplitter::sample_chunks(chunks_for_sampling(100), 0.1, 42).unwrap();
+        let same_seed = LineSplitter::sample_chunks(chunks_for_sampling(100), 0.1, 42).unwrap();
+        let other_seed = LineSplitter::sample_chunks(chunks_for_sampling(100), 0.1, 7).unwrap();
+
+        assert_eq!(10, sample.len());
+        assert_eq!(sample, same_seed);
+        assert_ne!(sample, other_seed);
+        assert!(
+            sample
+                .windows(2)
+                .all(|w| w[0].get_start_byte() < w[1].get_start_byte())
+        );
+    }
+
+    #[test]
+    fn test_sampling_chunks_of_a_single_file() {
+        let mut path: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        path.push("testdata");
+        path.push("text_with_newlines");
+        path.push("text_with_newlines.txt");
+        let chunks = LineSplitter::split_file(path, "", false, 100).unwrap();
+        let sample_size = (chunks.len() + 1) / 2;
+
+        let sample = LineSplitter::sample_chunks(chunks.clone(), 0.5, 1).unwrap();
+        let other_seed = LineSplitter::sample_chunks(chunks.clone(), 0.5, 2).unwrap();
+
+        assert_eq!(sample_size, sample.len());
+        assert_ne!(sample, other_seed);
+        assert_ne!(&chunks[..sample_size], &sample[..]);
+    }
+
+    #[test]
+    fn test_sampling_keeps_at_least_one_chunk() {
+        let sample = LineSplitter::sample_chunks(chunks_for_sampling(3), 0.01, 0).unwrap();
+
+        assert_eq!(1, sample.len());
+    }
+
+    #[test]
+    fn test_sampling_disabled() {
+        let sample = LineSplitter::sample_chunks(chunks_for_sampling(3), 0.0, 0).unwrap();
+
+        assert_eq!(3, sample.len());
+    }
+
+    #[test]
+    fn test_limiting_records() {
+        let mut path: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        path.push("testdata");
+        path.push("text_with_newlines");
+        path.push("text_with_newlines.txt");
+        let chunks = LineSplitter::split_file(path, "", false, 1024).unwrap();
+        assert_eq!(2, chunks.len());
+
+        let limited = LineSplitter::limit_records(chunks.clone(), 12).unwrap();
+        assert_eq!(2, limited.len());
+        assert_eq!(chunks[0], limited[0]);
+        assert_eq!(1003, limited[1].get_start_byte());
+        assert_eq!(1173, limited[1].get_end_byte());
+
+        // The same records are kept whatever order the chunks are in.
+        let mut reversed = chunks.clone();
+        reversed.reverse();
+        assert_eq!(limited, LineSplitter::limit_records(reversed, 12).unwrap());
+
+        let limited = LineSplitter::limit_records(chunks.clone(), 5).unwrap();
+        assert_eq!(1, limited.len());
+        assert_eq!(661, limited[0].get_end_byte());
+
+        assert_eq!(chunks, LineSplitter::limit_records(chunks.clone(), 0).unwrap());
+        assert_eq!(chunks, LineSplitter::limit_records(chunks.clone(), 100).unwrap());
+    }
+
+    #[test]
+    fn test_sampling_invalid_fraction() {
+        assert!(LineSplitter::sample_chunks(chunks_for_sampling(3), 1.5, 0).is_err());
     }
 }
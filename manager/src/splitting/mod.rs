@@ -3,13 +3,77 @@ mod reduce;
 
 use std::fmt;
 use std::fmt::Display;
+use std::fs;
+
+use std::path::PathBuf;
 
 use failure::*;
+use protobuf::RepeatedField;
 
 use heracles_proto::datatypes::{Job, Task};
+use settings::SETTINGS;
+
+/// `TaskPlan` is the full set of tasks a job is split into.
+///
+/// Each map task writes one intermediate file per partition, and the reduce task of each partition
+/// reads the intermediate files of that partition from every map task.
+pub struct TaskPlan {
+    pub map_tasks: Vec<Task>,
+    pub reduce_tasks: Vec<Task>,
+}
+
+/// Splits a job into map and reduce tasks, and wires the output of the map tasks into the input
+/// of the reduce tasks.
+pub fn split(job: &Job) -> Result<TaskPlan, Error> {
+    let mut map_tasks = map::split(job)?;
+    let mut reduce_tasks = reduce::split(job);
+    if reduce_tasks.is_empty() {
+        return Err(SplitterErrorKind::NoOutputFiles.into());
+    }
+    let intermediate_dir: String = SETTINGS.read().unwrap().get("intermediate_directory")?;
+
+    let partition_count = reduce_tasks.len() as u64;
+    for (map_index, task) in map_tasks.iter_mut().enumerate() {
+        let output_files = (0..partition_count)
+            .map(|partition| intermediate_path(&intermediate_dir, job, map_index, partition))
+            .collect();
+        task.set_partition_count(partition_count);
+        task.set_output_files(RepeatedField::from_vec(output_files));
+    }
+
+    let map_count = map_tasks.len();
+    for (partition, task) in reduce_tasks.iter_mut().enumerate() {
+        // The worker reads a comma separated list of input files for reduce tasks.
+        let input_files: Vec<String> = (0..map_count)
+            .map(|map_index| {
+                intermediate_path(&intermediate_dir, job, map_index, partition as u64)
+            })
+            .collect();
+        task.mut_input_chunk().set_path(input_files.join(","));
+    }
+
+    Ok(TaskPlan {
+        map_tasks,
+        reduce_tasks,
+    })
+}
+
+/// Returns the path of the intermediate file written by a map task for a partition.
+fn intermediate_path(dir: &str, job: &Job, map_index: usize, partition: u64) -> String {
+    let mut path = PathBuf::from(dir);
+    path.push(job.get_id());
+    path.push(format!("map_{}_partition_{}", map_index, partition));
+    path.to_string_lossy().to_string()
+}
 
-pub fn split(job: &Job) -> Result<Vec<Task>, Error> {
-    map::split(job)
+/// Removes the intermediate files written by the map tasks of a job, if there are any.
+pub fn remove_intermediate_files(job: &Job) -> Result<(), Error> {
+    let intermediate_dir: String = SETTINGS.read().unwrap().get("intermediate_directory")?;
+    let path = PathBuf::from(intermediate_dir).join(job.get_id());
+    if path.exists() {
+        fs::remove_dir_all(path).context(SplitterErrorKind::GenericIOError)?;
+    }
+    Ok(())
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
@@ -26,6 +90,10 @@ pub enum SplitterErrorKind {
     InvalidInputDataKind,
     #[fail(display = "Failed to access the output file directory.")]
     OutputDirectoryOpenFailed,
+    #[fail(display = "Sample fraction must be between 0 and 1.")]
+    InvalidSampleFraction,
+    #[fail(display = "Job must have at least one output file.")]
+    NoOutputFiles,
 }
 
 #[derive(Debug)]
@@ -68,3 +136,63 @@ impl From<Context<SplitterErrorKind>> for SplitterError {
         SplitterError { inner }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heracles_proto::datatypes::InputDataKind;
+
+    #[test]
+    fn test_split_task_plan() {
+        let mut dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        dir.push("testdata");
+        dir.push("text_with_newlines");
+        {
+            let mut settings = SETTINGS.write().unwrap();
+            settings.set("input_chunk_size", 1024).unwrap();
+            settings
+                .set("intermediate_directory", "/intermediate")
+                .unwrap();
+        }
+        let mut job = Job::new();
+        job.set_id("job".to_string());
+        job.set_input_directory(dir.to_str().unwrap().to_string());
+        job.set_input_kind(InputDataKind::DATA_TEXT_NEWLINES);
+        job.set_output_files(RepeatedField::from_vec(vec![
+            "/output/0".to_string(),
+            "/output/1".to_string(),
+            "/output/2".to_string(),
+        ]));
+
+        let plan = split(&job).unwrap();
+
+        assert_eq!(2, plan.map_tasks.len());
+        assert_eq!(3, plan.reduce_tasks.len());
+        assert!(
+            plan.map_tasks
+                .iter()
+                .all(|task| task.get_partition_count() == 3)
+        );
+        assert_eq!(
+            "/intermediate/job/map_1_partition_2",
+            plan.map_tasks[1].get_output_files()[2]
+        );
+        assert_eq!(
+            "/intermediate/job/map_0_partition_1,/intermediate/job/map_1_partition_1",
+            plan.reduce_tasks[1].get_input_chunk().get_path()
+        );
+        assert_eq!("/output/1", plan.reduce_tasks[1].get_output_files()[0]);
+    }
+
+    #[test]
+    fn test_split_without_output_files() {
+        let mut dir: PathBuf = env!("CARGO_MANIFEST_DIR").into();
+        dir.push("testdata");
+        dir.push("text_with_newlines");
+        let mut job = Job::new();
+        job.set_input_directory(dir.to_str().unwrap().to_string());
+        job.set_input_kind(InputDataKind::DATA_TEXT_NEWLINES);
+
+        assert!(split(&job).is_err());
+    }
+}
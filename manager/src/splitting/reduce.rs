@@ -16,9 +16,12 @@ pub fn split(job: &Job) -> Vec<Task> {
             task.set_id(Uuid::new_v4().to_string());
             task.set_job_id(job.get_id().to_string());
             task.set_status(TaskStatus::TASK_PENDING);
+            task.set_attempt(1);
+            task.set_priority(job.get_priority());
             task.set_kind(TaskKind::REDUCE);
             task.set_time_created(Utc::now().timestamp() as u64);
             task.set_output_files(protobuf::RepeatedField::from_vec(vec![file.to_string()]));
+            task.set_output_kind(job.get_output_kind());
             task.set_payload_path(job.get_payload_path().to_string());
             task
         })
@@ -1,10 +1,16 @@
+use std::cmp;
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
+use futures::future::{loop_fn, Loop};
 use protobuf;
 use protobuf::Message;
+use uuid::Uuid;
 
 use super::*;
 
@@ -14,8 +20,14 @@ const JOB_SAVE_FILE: &str = "request";
 const TASKS_DIR: &str = "tasks";
 const PENDING_MAP_DIR: &str = "pending_map_tasks";
 const PENDING_REDUCE_DIR: &str = "pending_reduce_tasks";
+const ATTEMPTS_DIR: &str = "attempts";
+const RECURRING_JOBS_DIR: &str = "recurring_jobs";
+
+/// How often the pending tasks are checked while waiting for them to be done.
+const TASKS_DONE_POLL_INTERVAL_SECS: u64 = 1;
 
 /// Save the state data in filesystem
+#[derive(Clone)]
 pub struct FileStore {
     path: PathBuf,
 }
@@ -24,6 +36,8 @@ impl FileStore {
     // Creates a new file backed state storing
     pub fn new(path: &PathBuf) -> Result<Self, StateError> {
         fs::create_dir_all(path.join(JOB_DIR)).context(StateErrorKind::JobsFolderCreationFailed)?;
+        fs::create_dir_all(path.join(RECURRING_JOBS_DIR))
+            .context(StateErrorKind::RecurringJobsFolderCreationFailed)?;
         Ok(FileStore { path: path.clone() })
     }
 
@@ -56,6 +70,39 @@ impl FileStore {
             .context(StateErrorKind::JobsFolderRemoveFailed)?)
     }
 
+    /// Creates the path to the file of the recurring job with the given `recurring_job_id`.
+    /// Recurring job IDs come from clients, so anything other than a UUID is rejected rather than
+    /// used as a path.
+    fn recurring_job_path(&self, recurring_job_id: &str) -> Result<PathBuf, StateError> {
+        Uuid::parse_str(recurring_job_id).context(StateErrorKind::InvalidRecurringJobId)?;
+        Ok(self.path.join(RECURRING_JOBS_DIR).join(recurring_job_id))
+    }
+
+    /// Writes the details of a task, without changing whether it is pending.
+    fn write_task(&self, task: &Task) -> Result<(), StateError> {
+        let serialized = task.write_to_bytes()
+            .context(StateErrorKind::TaskSerialisationFailed)?;
+        let task_file_path = self.job_dir_path(task.get_job_id())
+            .join(TASKS_DIR)
+            .join(task.get_id());
+        File::create(task_file_path)
+            .context(StateErrorKind::TaskWriteFailed)?
+            .write_all(&serialized)
+            .context(StateErrorKind::TaskWriteFailed)?;
+        Ok(())
+    }
+
+    /// Creates the path to the pending file of the given `task`.
+    fn pending_task_path(&self, task: &Task) -> PathBuf {
+        let mut pending_file_path = self.job_dir_path(task.get_job_id());
+        match task.get_kind() {
+            TaskKind::MAP => pending_file_path.push(PENDING_MAP_DIR),
+            TaskKind::REDUCE => pending_file_path.push(PENDING_REDUCE_DIR),
+        }
+        pending_file_path.push(task.get_id());
+        pending_file_path
+    }
+
     /// Lists pending tasks for a specified type of task in a job.
     fn list_pending_tasks(&self, job_id: &str, kind: TaskKind) -> Result<Vec<String>, StateError> {
         let job_dir_path = self.job_dir_path(job_id);
@@ -80,6 +127,27 @@ impl FileStore {
             .collect::<Result<Vec<String>, StateError>>()
     }
 
+    /// Returns a future which resolves once there are no pending tasks of the given type left in
+    /// the job. Tasks are removed from the pending list by the workers once they are done.
+    ///
+    /// The pending tasks are polled, blocking the thread the future is run on between checks.
+    fn tasks_done(
+        &self,
+        job_id: &str,
+        kind: TaskKind,
+    ) -> Box<Future<Item = (), Error = StateError> + Send> {
+        let store = self.clone();
+        let job_id = job_id.to_string();
+        Box::new(loop_fn((), move |_| {
+            let pending = store.list_pending_tasks(&job_id, kind)?;
+            if pending.is_empty() {
+                return Ok(Loop::Break(()));
+            }
+            thread::sleep(Duration::from_secs(TASKS_DONE_POLL_INTERVAL_SECS));
+            Ok(Loop::Continue(()))
+        }))
+    }
+
     /// Gets the full task details of map or reduce tasks which have not yet completed.
     fn pending_tasks_data(&self, job_id: &str, kind: TaskKind) -> Result<Vec<Task>, StateError> {
         let tasks_dir_path = self.job_dir_path(job_id).join(TASKS_DIR);
@@ -106,34 +174,62 @@ impl State for FileStore {
 
         let serialized = job.write_to_bytes()
             .context(StateErrorKind::JobSerialisationFailed)?;
-        Ok(File::create(&job_dir_path.join(JOB_SAVE_FILE))
-            .context(StateErrorKind::JobWriteFailed)?
-            .write_all(&serialized)
-            .context(StateErrorKind::JobWriteFailed)?)
+        // Workers read the job to check whether it was cancelled, so it must never be seen half
+        // written.
+        write_file_atomic(&job_dir_path.join(JOB_SAVE_FILE), &serialized)
+            .context(StateErrorKind::JobWriteFailed)?;
+        Ok(())
     }
 
-    fn save_task(&self, task: &Task) -> Result<(), StateError> {
-        let job_dir_path = self.job_dir_path(task.get_job_id());
-        let task_id = task.get_id();
+    fn get_job(&self, job_id: &str) -> Result<Job, StateError> {
+        let job_file_path = self.job_dir_path(job_id).join(JOB_SAVE_FILE);
+        if !job_file_path.exists() {
+            return Err(StateErrorKind::JobNotFound.into());
+        }
 
-        let serialized = task.write_to_bytes()
-            .context(StateErrorKind::TaskSerialisationFailed)?;
-        File::create(&job_dir_path.join(TASKS_DIR).join(task_id))
-            .context(StateErrorKind::TaskWriteFailed)?
-            .write_all(&serialized)
-            .context(StateErrorKind::TaskWriteFailed)?;
+        let mut f = File::open(job_file_path).context(StateErrorKind::GenericIOError)?;
+        Ok(protobuf::core::parse_from_reader::<Job>(&mut f)
+            .context(StateErrorKind::JobDeserialisationFailed)?)
+    }
 
-        // Save to either map or reduce pending tasks
-        let mut pending_file_path = job_dir_path.clone();
-        match task.get_kind() {
-            TaskKind::MAP => pending_file_path.push(PENDING_MAP_DIR),
-            TaskKind::REDUCE => pending_file_path.push(PENDING_REDUCE_DIR),
+    fn job_ids(&self) -> Result<Vec<String>, StateError> {
+        let mut job_ids = Vec::new();
+        let entries =
+            fs::read_dir(self.path.join(JOB_DIR)).context(StateErrorKind::JobsListFailed)?;
+        for entry in entries {
+            let job_id = entry
+                .context(StateErrorKind::GenericIOError)?
+                .file_name()
+                .into_string()
+                .unwrap();
+            // The directory of a job is created before the job is written to it.
+            if self.job_dir_path(&job_id).join(JOB_SAVE_FILE).exists() {
+                job_ids.push(job_id);
+            }
         }
-        pending_file_path.push(task_id);
+        Ok(job_ids)
+    }
+
+    fn job_tasks(&self, job: &Job) -> Result<Vec<Task>, StateError> {
+        let tasks_dir_path = self.job_dir_path(job.get_id()).join(TASKS_DIR);
+        fs::read_dir(&tasks_dir_path)
+            .context(StateErrorKind::TasksListFailed)?
+            .map(|entry| {
+                let path = entry.context(StateErrorKind::GenericIOError)?.path();
+                let mut f = File::open(path).context(StateErrorKind::TaskFileOpenFailed)?;
+                Ok(protobuf::core::parse_from_reader::<Task>(&mut f)
+                    .context(StateErrorKind::TaskDeserialisationFailed)?)
+            })
+            .collect::<Result<Vec<Task>, StateError>>()
+    }
+
+    fn save_task(&self, task: &Task) -> Result<(), StateError> {
+        self.write_task(task)?;
 
-        File::create(pending_file_path)
+        // Save to either map or reduce pending tasks
+        File::create(self.pending_task_path(task))
             .context(StateErrorKind::PendingTaskWriteFailed)?
-            .write_all(&task_id.as_bytes())
+            .write_all(&task.get_id().as_bytes())
             .context(StateErrorKind::PendingTaskWriteFailed)?;
         Ok(())
     }
@@ -146,11 +242,130 @@ impl State for FileStore {
         self.pending_tasks_data(job.get_id().into(), TaskKind::REDUCE)
     }
 
-    fn map_done(&self, job: &Job) -> Box<Future<Item = (), Error = StateError>> {
-        unimplemented!()
+    fn map_done(&self, job: &Job) -> Box<Future<Item = (), Error = StateError> + Send> {
+        self.tasks_done(job.get_id(), TaskKind::MAP)
     }
 
-    fn reduce_done(&self, job: &Job) -> Box<Future<Item = (), Error = StateError>> {
-        unimplemented!()
+    fn reduce_done(&self, job: &Job) -> Box<Future<Item = (), Error = StateError> + Send> {
+        self.tasks_done(job.get_id(), TaskKind::REDUCE)
+    }
+
+    fn next_attempt(&self, task: &Task) -> Result<u32, StateError> {
+        let attempts_dir_path = self.job_dir_path(task.get_job_id()).join(ATTEMPTS_DIR);
+        let attempts_file_path = attempts_dir_path.join(task.get_id());
+
+        let mut highest = cmp::max(task.get_attempt(), 1);
+        if attempts_file_path.exists() {
+            let saved: u32 = fs::read_to_string(&attempts_file_path)
+                .context(StateErrorKind::TaskAttemptsReadFailed)?
+                .trim()
+                .parse()
+                .context(StateErrorKind::TaskAttemptsReadFailed)?;
+            highest = cmp::max(highest, saved);
+        }
+
+        let next = highest + 1;
+        fs::create_dir_all(&attempts_dir_path).context(StateErrorKind::TaskAttemptsWriteFailed)?;
+        fs::write(&attempts_file_path, next.to_string())
+            .context(StateErrorKind::TaskAttemptsWriteFailed)?;
+        Ok(next)
+    }
+
+    fn cancel_pending_tasks(&self, job: &Job) -> Result<(), StateError> {
+        for kind in &[TaskKind::MAP, TaskKind::REDUCE] {
+            for mut task in self.pending_tasks_data(job.get_id(), *kind)? {
+                task.set_status(TaskStatus::TASK_CANCELLED);
+                self.write_task(&task)?;
+                fs::remove_file(self.pending_task_path(&task))
+                    .context(StateErrorKind::PendingTaskRemoveFailed)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn save_recurring_job(&self, recurring_job: &RecurringJob) -> Result<(), StateError> {
+        let serialized = recurring_job
+            .write_to_bytes()
+            .context(StateErrorKind::RecurringJobSerialisationFailed)?;
+        let path = self.recurring_job_path(recurring_job.get_id())?;
+        write_file_atomic(&path, &serialized).context(StateErrorKind::RecurringJobWriteFailed)?;
+        Ok(())
+    }
+
+    fn get_recurring_job(&self, recurring_job_id: &str) -> Result<RecurringJob, StateError> {
+        let path = self.recurring_job_path(recurring_job_id)?;
+        if !path.exists() {
+            return Err(StateErrorKind::RecurringJobNotFound.into());
+        }
+
+        let mut f = File::open(path).context(StateErrorKind::GenericIOError)?;
+        Ok(protobuf::core::parse_from_reader::<RecurringJob>(&mut f)
+            .context(StateErrorKind::RecurringJobDeserialisationFailed)?)
+    }
+
+    fn recurring_jobs(&self) -> Result<Vec<RecurringJob>, StateError> {
+        let mut recurring_jobs = Vec::new();
+        for entry in fs::read_dir(self.path.join(RECURRING_JOBS_DIR))
+            .context(StateErrorKind::RecurringJobsListFailed)?
+        {
+            let path = entry.context(StateErrorKind::GenericIOError)?.path();
+            if path.extension().is_some() {
+                // Skip recurring jobs which are still being written.
+                continue;
+            }
+            let mut f = File::open(path).context(StateErrorKind::GenericIOError)?;
+            recurring_jobs.push(protobuf::core::parse_from_reader::<RecurringJob>(&mut f)
+                .context(StateErrorKind::RecurringJobDeserialisationFailed)?);
+        }
+        Ok(recurring_jobs)
+    }
+
+    fn remove_recurring_job(&self, recurring_job_id: &str) -> Result<(), StateError> {
+        let path = self.recurring_job_path(recurring_job_id)?;
+        if !path.exists() {
+            return Err(StateErrorKind::RecurringJobNotFound.into());
+        }
+        Ok(fs::remove_file(path).context(StateErrorKind::RecurringJobRemoveFailed)?)
+    }
+}
+
+/// Writes a file by renaming a temporary file into place, so readers never see it half written.
+/// The temporary file is named after the file with an extension added.
+fn write_file_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".{}.tmp", Uuid::new_v4()));
+    let tmp_path = PathBuf::from(tmp_path);
+    let result = File::create(&tmp_path)
+        .and_then(|mut file| file.write_all(data))
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_map_done() {
+        let state_dir = env::temp_dir().join(format!("heracles-state-{}", Uuid::new_v4()));
+        let store = FileStore::new(&state_dir).unwrap();
+        let mut job = Job::new();
+        job.set_id("job".to_string());
+        store.save_job(&job).unwrap();
+        let mut task = Task::new();
+        task.set_id("map-0".to_string());
+        task.set_job_id(job.get_id().to_string());
+        task.set_kind(TaskKind::MAP);
+        store.save_task(&task).unwrap();
+
+        // Removing the pending file is how a worker marks a task as done.
+        fs::remove_file(store.pending_task_path(&task)).unwrap();
+        assert!(store.map_done(&job).wait().is_ok());
+        assert!(store.reduce_done(&job).wait().is_ok());
+        fs::remove_dir_all(state_dir).unwrap();
     }
 }
@@ -1,6 +1,6 @@
 mod file;
 
-use self::file::FileStore;
+pub use self::file::FileStore;
 
 use std::fmt;
 use std::fmt::Display;
@@ -8,23 +8,43 @@ use std::fmt::Display;
 use failure::*;
 use futures::Future;
 
-use heracles_proto::datatypes::{Job, Task, TaskKind};
+use heracles_proto::datatypes::{Job, RecurringJob, Task, TaskKind, TaskStatus};
 
 #[allow(doc_markdown)]
 /// Interface for creating connections to state stores, such as etcd or TiKV etc.
 pub trait State {
     /// Serialize the job and save it in the state store so it can be loaded later.
     fn save_job(&self, job: &Job) -> Result<(), StateError>;
+    /// Loads a job previously saved with `save_job`.
+    fn get_job(&self, job_id: &str) -> Result<Job, StateError>;
+    /// List of the IDs of all saved jobs, whether they have finished or not.
+    fn job_ids(&self) -> Result<Vec<String>, StateError>;
     /// Adds a task to the list of tasks and add it to pending
     fn save_task(&self, task: &Task) -> Result<(), StateError>;
+    /// List of all tasks of a specific job, whether they are pending or not.
+    fn job_tasks(&self, job: &Job) -> Result<Vec<Task>, StateError>;
     /// List of pending map tasks for a specific job.
     fn pending_map_tasks(&self, job: &Job) -> Result<Vec<Task>, StateError>;
     /// List of pending reduce tasks.
     fn pending_reduce_tasks(&self, job: &Job) -> Result<Vec<Task>, StateError>;
-    /// Returns a future when all map tasks are done.
-    fn map_done(&self, job: &Job) -> Box<Future<Item = (), Error = StateError>>;
-    /// Returns a future when all reduce tasks are done.
-    fn reduce_done(&self, job: &Job) -> Box<Future<Item = (), Error = StateError>>;
+    /// Returns a future which resolves once all map tasks are done.
+    fn map_done(&self, job: &Job) -> Box<Future<Item = (), Error = StateError> + Send>;
+    /// Returns a future which resolves once all reduce tasks are done.
+    fn reduce_done(&self, job: &Job) -> Box<Future<Item = (), Error = StateError> + Send>;
+    /// Reserves a number for a new attempt at a task, higher than that of any attempt issued for
+    /// it before. The saved task only holds the attempt last reported by a worker, so the highest
+    /// attempt issued is kept separately.
+    fn next_attempt(&self, task: &Task) -> Result<u32, StateError>;
+    /// Marks all pending tasks of a job as cancelled and removes them from the pending tasks.
+    fn cancel_pending_tasks(&self, job: &Job) -> Result<(), StateError>;
+    /// Serialize the recurring job and save it in the state store, replacing any earlier version.
+    fn save_recurring_job(&self, recurring_job: &RecurringJob) -> Result<(), StateError>;
+    /// Loads a recurring job previously saved with `save_recurring_job`.
+    fn get_recurring_job(&self, recurring_job_id: &str) -> Result<RecurringJob, StateError>;
+    /// List of all saved recurring jobs.
+    fn recurring_jobs(&self) -> Result<Vec<RecurringJob>, StateError>;
+    /// Removes a recurring job from the state store.
+    fn remove_recurring_job(&self, recurring_job_id: &str) -> Result<(), StateError>;
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
@@ -45,10 +65,18 @@ pub enum StateErrorKind {
     ReduceTasksFolderCreationFailed,
     #[fail(display = "Unable to list pending tasks")]
     PendingTasksListFailed,
+    #[fail(display = "Unable to list jobs")]
+    JobsListFailed,
+    #[fail(display = "Unable to list tasks")]
+    TasksListFailed,
     #[fail(display = "An unknown I/O error has occurred.")]
     GenericIOError,
     #[fail(display = "Failed to serialise the job proto.")]
     JobSerialisationFailed,
+    #[fail(display = "Failed to deserialise the job proto.")]
+    JobDeserialisationFailed,
+    #[fail(display = "No job with the given ID exists")]
+    JobNotFound,
     #[fail(display = "Failed to serialise the task proto.")]
     TaskSerialisationFailed,
     #[fail(display = "Failed to deserialise the task proto.")]
@@ -61,6 +89,28 @@ pub enum StateErrorKind {
     TaskWriteFailed,
     #[fail(display = "Failed to create pending task")]
     PendingTaskWriteFailed,
+    #[fail(display = "Failed to remove pending task")]
+    PendingTaskRemoveFailed,
+    #[fail(display = "Failed to read the attempts of a task")]
+    TaskAttemptsReadFailed,
+    #[fail(display = "Failed to write the attempts of a task")]
+    TaskAttemptsWriteFailed,
+    #[fail(display = "Unable to create required recurring jobs folder")]
+    RecurringJobsFolderCreationFailed,
+    #[fail(display = "Unable to list recurring jobs")]
+    RecurringJobsListFailed,
+    #[fail(display = "Failed to serialise the recurring job proto.")]
+    RecurringJobSerialisationFailed,
+    #[fail(display = "Failed to deserialise the recurring job proto.")]
+    RecurringJobDeserialisationFailed,
+    #[fail(display = "No recurring job with the given ID exists")]
+    RecurringJobNotFound,
+    #[fail(display = "Recurring job ID is not a valid UUID")]
+    InvalidRecurringJobId,
+    #[fail(display = "Failed to write recurring job")]
+    RecurringJobWriteFailed,
+    #[fail(display = "Failed to remove recurring job")]
+    RecurringJobRemoveFailed,
     #[fail(display = "Failed operation.")]
     OperationFailed,
 }
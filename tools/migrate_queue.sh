@@ -0,0 +1,25 @@
+#!/usr/bin/env sh
+
+# Removes the heracles_tasks queue used before tasks had priorities. The
+# manager and workers now use heracles_priority_tasks, as a queue can't be
+# redeclared with the x-max-priority argument.
+#
+# Stop the workers once the old queue is drained before running this. Tasks
+# still in the queue are lost, but the manager sends unstarted tasks again
+# when it resumes jobs on startup. Set RABBITMQCTL to run rabbitmqctl
+# differently, e.g. "docker exec rabbit rabbitmqctl" for the demo broker.
+
+RABBITMQCTL=${RABBITMQCTL:-rabbitmqctl}
+QUEUE=${1:-heracles_tasks}
+
+MESSAGES=$($RABBITMQCTL -q list_queues name messages | awk -v q="$QUEUE" '$1 == q { print $2 }')
+if [ -z "$MESSAGES" ]; then
+    echo "queue $QUEUE does not exist"
+    exit 0
+fi
+if [ "$MESSAGES" -ne 0 ] && [ "$FORCE" != "1" ]; then
+    echo "queue $QUEUE still has $MESSAGES messages, set FORCE=1 to remove it anyway"
+    exit 1
+fi
+
+$RABBITMQCTL delete_queue "$QUEUE"
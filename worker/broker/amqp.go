@@ -1,6 +1,9 @@
 package broker
 
 import (
+	"fmt"
+	"sync"
+
 	log "github.com/golang/glog"
 	"github.com/golang/protobuf/proto"
 	"github.com/pkg/errors"
@@ -21,6 +24,7 @@ type AMQPConnection struct {
 	conn      *amqp.Connection
 	ch        channel
 	tags      map[string]*amqp.Delivery
+	tagsMu    sync.Mutex
 	tasks     chan *datatypes.Task
 	queueName string
 }
@@ -51,13 +55,15 @@ func NewAMQP(addr string) (*AMQPConnection, error) {
 
 	queueName := settings.GetString("broker.queue_name")
 
+	// The arguments must match the ones the manager declares the queue with.
+	args := amqp.Table{"x-max-priority": int32(settings.GetInt("broker.max_priority"))}
 	if _, err = ch.QueueDeclare(
 		queueName,
 		true,
 		false,
 		false,
 		false,
-		nil,
+		args,
 	); err != nil {
 		return nil, errors.Wrap(err, "unable to declare a queue")
 	}
@@ -70,9 +76,25 @@ func NewAMQP(addr string) (*AMQPConnection, error) {
 	}, nil
 }
 
+// deliveryKey identifies the delivery of a task. A task can be delivered more
+// than once at the same time when the manager launches a duplicate attempt of
+// it, so the attempt is part of the key.
+func deliveryKey(task *datatypes.Task) string {
+	return fmt.Sprintf("%s/%d", task.GetId(), task.GetAttempt())
+}
+
+// delivery returns the delivery of a task, and forgets about it.
+func (c *AMQPConnection) delivery(task *datatypes.Task) (*amqp.Delivery, bool) {
+	c.tagsMu.Lock()
+	defer c.tagsMu.Unlock()
+	d, ok := c.tags[deliveryKey(task)]
+	delete(c.tags, deliveryKey(task))
+	return d, ok
+}
+
 // Done implementation
 func (c *AMQPConnection) Done(task *datatypes.Task) error {
-	if d, ok := c.tags[task.GetId()]; ok {
+	if d, ok := c.delivery(task); ok {
 		if err := d.Ack(false); err != nil {
 			log.Warningf("can't ack task %s: %v", task.GetId(), err)
 			return ErrAckFailure
@@ -84,8 +106,9 @@ func (c *AMQPConnection) Done(task *datatypes.Task) error {
 
 // Failed implementation
 func (c *AMQPConnection) Failed(task *datatypes.Task) error {
-	if d, ok := c.tags[task.GetId()]; ok {
-		if err := d.Nack(false, true); err != nil {
+	if d, ok := c.delivery(task); ok {
+		// The task is not requeued, as the manager retries failed tasks.
+		if err := d.Nack(false, false); err != nil {
 			log.Warningf("can't nack task %s: %v", task.GetId(), err)
 			return ErrAckFailure
 		}
@@ -112,12 +135,15 @@ func (c *AMQPConnection) Listen() error {
 	}
 
 	for msg := range msgs {
+		msg := msg
 		task := &datatypes.Task{}
 		if err := proto.Unmarshal(msg.Body, task); err != nil {
 			return errors.Wrap(err, "unable to parse task")
 		}
 		log.V(2).Infof("got task %s with tag %d", task.GetId(), msg.DeliveryTag)
-		c.tags[task.GetId()] = &msg
+		c.tagsMu.Lock()
+		c.tags[deliveryKey(task)] = &msg
+		c.tagsMu.Unlock()
 		c.tasks <- task
 	}
 
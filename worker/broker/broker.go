@@ -15,7 +15,8 @@ type Broker interface {
 	// Done marks which messages are done for and can be acknowledged
 	Done(*datatypes.Task) error
 
-	// Failed marks a message to a broker than it has failed.
+	// Failed marks a message to a broker than it has failed. The message is
+	// not redelivered.
 	Failed(*datatypes.Task) error
 }
 
@@ -3,8 +3,12 @@
 package runner
 
 import (
+	"bytes"
+	"context"
 	"fmt"
 	"io"
+	"io/ioutil"
+	"os"
 	"os/exec"
 	"sync"
 	"time"
@@ -17,6 +21,10 @@ import (
 	"github.com/cpssd/heracles/worker/state"
 )
 
+// errTaskCancelled is returned when a task is stopped because its job was
+// cancelled.
+var errTaskCancelled = errors.New("job cancelled")
+
 // Runner object which listens for broker connections, runs the
 // task and saves the state to state store.
 type Runner struct {
@@ -49,13 +57,31 @@ func (r Runner) Run() error {
 		go func(task *datatypes.Task) {
 			defer wg.Done()
 
+			if r.jobCancelled(task) {
+				log.Infof("dropping task %s of cancelled job %s", task.GetId(), task.GetJobId())
+				r.cancelTask(task)
+				return
+			}
 			if err := r.handleTask(task); err != nil {
-				r.failTask(task)
+				if errors.Cause(err) == state.ErrSuperseded {
+					r.discardTask(task)
+					return
+				}
+				if errors.Cause(err) == errTaskCancelled {
+					log.Infof("stopped task %s of cancelled job %s", task.GetId(), task.GetJobId())
+					r.cancelTask(task)
+					return
+				}
+				r.failTask(task, err)
 				log.Warningf("unable to run task: %v", err)
 				return
 			}
 			if err := r.succeedTask(task); err != nil {
-				r.failTask(task)
+				if errors.Cause(err) == state.ErrSuperseded {
+					r.discardTask(task)
+					return
+				}
+				r.failTask(task, err)
 				log.Warningf("unable to succeed task: %v", err)
 			}
 		}(task)
@@ -77,28 +103,78 @@ func (r Runner) handleTask(task *datatypes.Task) error {
 		return err
 	}
 
-	cmd, err := r.prepareCmd(task)
+	progressFile, err := ioutil.TempFile("", "heracles_progress_")
+	if err != nil {
+		return errors.Wrap(err, "unable to create progress file")
+	}
+	progressFile.Close()
+	defer os.Remove(progressFile.Name())
+
+	manifestFile, err := ioutil.TempFile("", "heracles_manifest_")
+	if err != nil {
+		return errors.Wrap(err, "unable to create manifest file")
+	}
+	manifestFile.Close()
+	defer os.Remove(manifestFile.Name())
+
+	// The payload is killed if the job is cancelled while it is running.
+	ctx, cancel := context.WithCancel(context.Background())
+	defer cancel()
+
+	cmd, err := r.prepareCmd(ctx, task, progressFile.Name(), manifestFile.Name())
 	if err != nil {
 		return err
 	}
 
-	out, err := cmd.CombinedOutput()
+	stopWatching := make(chan struct{})
+	var watching sync.WaitGroup
+	watching.Add(1)
+	go func() {
+		defer watching.Done()
+		r.watchProgress(task, progressFile.Name(), stopWatching, cancel)
+	}()
+	// Only stdout holds the results, anything the payload logs goes to stderr.
+	var stderr bytes.Buffer
+	cmd.Stderr = &stderr
+	out, err := cmd.Output()
+	close(stopWatching)
+	watching.Wait()
+	if ctx.Err() != nil {
+		return errTaskCancelled
+	}
 	if err != nil {
-		log.Infof("output: %s", string(out))
+		log.Infof("output: %s", out)
+		log.Infof("stderr: %s", stderr.String())
 		return err
 	}
 	log.V(2).Infof("Output from binary: %s", out)
+	if stderr.Len() > 0 {
+		log.V(1).Infof("Stderr from binary: %s", stderr.String())
+	}
 
-	if err := saveResults(out, task); err != nil {
-		return err
+	if metrics, err := readMetrics(progressFile.Name()); err != nil {
+		log.Warningf("no metrics for task %s: %v", task.GetId(), err)
+	} else {
+		task.Metrics = metrics
 	}
 
-	return nil
+	// Don't write intermediate files for a job whose files were cleaned up.
+	if r.jobCancelled(task) {
+		return errTaskCancelled
+	}
+	// Another attempt at the task may have finished first.
+	if superseded, err := r.st.Superseded(task); err != nil {
+		log.Warningf("unable to check if task %s was superseded: %v", task.GetId(), err)
+	} else if superseded {
+		return state.ErrSuperseded
+	}
+
+	return saveResults(out, task, manifestFile.Name())
 }
 
 // prepareCmd prepares a command to run. It gives it input in a correct
 // format and returns a exec.Cmd ready to be ran.
-func (r Runner) prepareCmd(task *datatypes.Task) (*exec.Cmd, error) {
+func (r Runner) prepareCmd(ctx context.Context, task *datatypes.Task, progressPath, manifestPath string) (*exec.Cmd, error) {
 	// Check is libcerberus library
 
 	if err := sanityCheck(task.GetPayloadPath()); err != nil {
@@ -111,26 +187,96 @@ func (r Runner) prepareCmd(task *datatypes.Task) (*exec.Cmd, error) {
 	args := []string{}
 	if task.GetKind() == datatypes.TaskKind_MAP {
 		args = append(args, "map", fmt.Sprintf("--partition_count=%d", task.GetPartitionCount()))
+		args = append(args, fmt.Sprintf("--input_kind=%s", task.GetInputKind().String()))
+		args = append(args, "--stream")
 		in, err = mapReader(task.GetInputChunk())
 	} else {
-		args = append(args, "reduce")
+		args = append(args, "reduce", fmt.Sprintf("--output_kind=%s", task.GetOutputKind().String()))
+		args = append(args, fmt.Sprintf("--manifest_file=%s", manifestPath))
 		in, err = reduceReader(task.GetInputChunk())
 	}
 	if err != nil {
 		return nil, err
 	}
+	args = append(args, fmt.Sprintf("--progress_file=%s", progressPath))
+	args = append(args, fmt.Sprintf("--protocol_version=%d", protocolVersion))
 
-	cmd := exec.Command(task.GetPayloadPath(), args...)
+	cmd := exec.CommandContext(ctx, task.GetPayloadPath(), args...)
 	cmd.Stdin = in
 
 	return cmd, nil
 }
 
+// watchProgress periodically reads the progress reported by the payload and
+// saves it to the state store, until stop is closed. If the job of the task is
+// cancelled in the meantime, cancel is called to stop the payload.
+func (r Runner) watchProgress(task *datatypes.Task, progressPath string, stop <-chan struct{}, cancel func()) {
+	ticker := time.NewTicker(progressInterval)
+	defer ticker.Stop()
+
+	for {
+		select {
+		case <-stop:
+			return
+		case <-ticker.C:
+			if r.jobCancelled(task) {
+				cancel()
+				return
+			}
+			progress, err := readProgress(progressPath)
+			if err != nil {
+				log.V(2).Infof("no progress for task %s: %v", task.GetId(), err)
+				continue
+			}
+			task.Progress = progress
+			if err := r.st.SaveProgress(task); err != nil {
+				log.Warningf("unable to save progress for task %s: %v", task.GetId(), err)
+			}
+		}
+	}
+}
+
+// jobCancelled checks whether the job of the task was cancelled. If this can't
+// be checked, the job is assumed to still be running.
+func (r Runner) jobCancelled(task *datatypes.Task) bool {
+	cancelled, err := r.st.JobCancelled(task.GetJobId())
+	if err != nil {
+		log.Warningf("unable to check if job %s was cancelled: %v", task.GetJobId(), err)
+		return false
+	}
+	return cancelled
+}
+
+// cancelTask marks the task as cancelled, and acknowledges it to the broker so
+// it is not delivered again.
+func (r Runner) cancelTask(task *datatypes.Task) {
+	task.TimeDone = uint64(time.Now().Unix())
+	task.Status = datatypes.TaskStatus_TASK_CANCELLED
+
+	// The manager may have cancelled the task already.
+	if err := r.st.SaveProgress(task); err != nil && errors.Cause(err) != state.ErrSuperseded {
+		log.Warningf("unable to save progress for task %s: %v", task.GetId(), err)
+	}
+	if err := r.br.Done(task); err != nil {
+		log.Errorf("unable to tell the broker the task %s was cancelled: %v", task.GetId(), err)
+	}
+}
+
+// discardTask drops an attempt at a task which is no longer needed, without
+// touching the state of the task.
+func (r Runner) discardTask(task *datatypes.Task) {
+	log.Infof("discarding attempt %d at task %s, as it was superseded", task.GetAttempt(), task.GetId())
+	if err := r.br.Done(task); err != nil {
+		log.Errorf("unable to tell the broker the task %s was discarded: %v", task.GetId(), err)
+	}
+}
+
 // fail tasks marks the task as failed, notifies the broker and the state
-// store.
-func (r Runner) failTask(task *datatypes.Task) error {
+// store. The manager decides whether the task is retried.
+func (r Runner) failTask(task *datatypes.Task, cause error) error {
 	task.TimeDone = uint64(time.Now().Unix())
 	task.Status = datatypes.TaskStatus_TASK_FAILED
+	task.FailureDetails = cause.Error()
 
 	if err := r.st.SaveProgress(task); err != nil {
 		log.Warningf("unable to save save progress for task %s: %v", task.GetId(), err)
@@ -51,6 +51,14 @@ func (s *stubBrokerState) SaveProgress(task *datatypes.Task) error {
 	return nil
 }
 
+func (s *stubBrokerState) JobCancelled(jobID string) (bool, error) {
+	return false, nil
+}
+
+func (s *stubBrokerState) Superseded(task *datatypes.Task) (bool, error) {
+	return false, nil
+}
+
 // TODO: !!!!! CHANGE THE TEST PATHS TO RELATIVE !!!!
 var tasks = []*datatypes.Task{
 	{
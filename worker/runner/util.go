@@ -9,8 +9,11 @@ import (
 	"io/ioutil"
 	"os"
 	"os/exec"
+	"path/filepath"
+	"sort"
 	"strconv"
 	"strings"
+	"time"
 
 	log "github.com/golang/glog"
 	"github.com/pkg/errors"
@@ -18,13 +21,122 @@ import (
 	"github.com/cpssd/heracles/proto/datatypes"
 )
 
+// protocolVersion is the version of the JSON layout used to talk to the
+// payload. It must match libcerberus::PROTOCOL_VERSION.
+const protocolVersion = 2
+
+// progressInterval is how often the progress reported by a payload is read.
+const progressInterval = 10 * time.Second
+
+// manifestSuffix is appended to the path of a final output file to get the
+// path of its manifest. It must match libcerberus::manifest::manifest_path.
+const manifestSuffix = ".manifest"
+
+// payloadProgress is the progress report written by libcerberus payloads.
+type payloadProgress struct {
+	Phase            string         `json:"phase"`
+	RecordsProcessed uint64         `json:"records_processed"`
+	BytesConsumed    uint64         `json:"bytes_consumed"`
+	TimeUpdated      uint64         `json:"time_updated"`
+	Metrics          payloadMetrics `json:"metrics"`
+}
+
+// payloadMetrics are the metrics included in the progress report. They are
+// only complete once the payload has finished.
+type payloadMetrics struct {
+	ReadInputMs   uint64 `json:"read_input_ms"`
+	MapMs         uint64 `json:"map_ms"`
+	PartitionMs   uint64 `json:"partition_ms"`
+	ReduceMs      uint64 `json:"reduce_ms"`
+	WriteOutputMs uint64 `json:"write_output_ms"`
+	InputRecords  uint64 `json:"input_records"`
+	OutputRecords uint64 `json:"output_records"`
+	PeakRecords   uint64 `json:"peak_records"`
+}
+
+// loadProgress reads the latest progress report written by the payload.
+func loadProgress(path string) (*payloadProgress, error) {
+	buf, err := ioutil.ReadFile(path)
+	if err != nil {
+		return nil, errors.Wrap(err, "unable to read progress file")
+	}
+	p := &payloadProgress{}
+	if err := json.Unmarshal(buf, p); err != nil {
+		return nil, errors.Wrap(err, "unable to parse progress")
+	}
+	return p, nil
+}
+
+// readProgress reads the latest progress report written by the payload.
+func readProgress(path string) (*datatypes.TaskProgress, error) {
+	p, err := loadProgress(path)
+	if err != nil {
+		return nil, err
+	}
+	return &datatypes.TaskProgress{
+		Phase:            p.Phase,
+		RecordsProcessed: p.RecordsProcessed,
+		BytesConsumed:    p.BytesConsumed,
+		TimeUpdated:      p.TimeUpdated,
+	}, nil
+}
+
+// readMetrics reads the metrics from the final progress report written by the
+// payload.
+func readMetrics(path string) (*datatypes.TaskMetrics, error) {
+	p, err := loadProgress(path)
+	if err != nil {
+		return nil, err
+	}
+	return &datatypes.TaskMetrics{
+		ReadInputMs:   p.Metrics.ReadInputMs,
+		MapMs:         p.Metrics.MapMs,
+		PartitionMs:   p.Metrics.PartitionMs,
+		ReduceMs:      p.Metrics.ReduceMs,
+		WriteOutputMs: p.Metrics.WriteOutputMs,
+		InputRecords:  p.Metrics.InputRecords,
+		OutputRecords: p.Metrics.OutputRecords,
+		PeakRecords:   p.Metrics.PeakRecords,
+	}, nil
+}
+
 // sanityCheck checks is a payload a valid libcerberus/libheracles
-// binary.
+// binary which speaks the same protocol version as the worker.
 func sanityCheck(payloadPath string) error {
-	out, err := exec.Command(payloadPath, "sanity-check").CombinedOutput()
-	if err != nil || string(out) != "sanity located" {
+	out, err := exec.Command(payloadPath, "sanity-check").Output()
+	if err != nil {
 		return errors.Wrap(err, "sanity check failed")
 	}
+	return checkSanityOutput(string(out))
+}
+
+// checkSanityOutput checks the output of the sanity-check subcommand.
+// Payloads built before the protocol was versioned don't report a version,
+// and don't understand the flags the worker passes them.
+func checkSanityOutput(out string) error {
+	lines := strings.Split(strings.TrimSpace(out), "\n")
+	if lines[0] != "sanity located" {
+		return errors.New("sanity check failed")
+	}
+
+	version := 0
+	for _, line := range lines[1:] {
+		if !strings.HasPrefix(line, "protocol_version ") {
+			continue
+		}
+		v, err := strconv.Atoi(strings.TrimPrefix(line, "protocol_version "))
+		if err != nil {
+			return errors.Wrap(err, "unable to parse payload protocol version")
+		}
+		version = v
+	}
+
+	if version == 0 {
+		return errors.Errorf("payload predates protocol versioning, expected version %d", protocolVersion)
+	}
+	if version != protocolVersion {
+		return errors.Errorf("payload uses protocol version %d, expected %d", version, protocolVersion)
+	}
 	return nil
 }
 
@@ -56,7 +168,12 @@ func mapReader(in *datatypes.InputChunk) (io.Reader, error) {
 		return nil, errors.Wrap(err, "unable to read input data")
 	}
 
-	out, err := json.Marshal(&kv{Key: in.GetPath(), Value: string(buf)})
+	out, err := json.Marshal(&mapInput{
+		Key:    in.GetPath(),
+		Value:  string(buf),
+		Tag:    in.GetTag(),
+		Header: in.GetHeader(),
+	})
 	if err != nil {
 		return nil, errors.Wrap(err, "unable to parse map input")
 	}
@@ -100,11 +217,20 @@ func reduceReader(in *datatypes.InputChunk) (io.Reader, error) {
 		}
 	}
 
+	// Keys are passed to the payload in order rather than in map order, so
+	// duplicate and retried attempts at a task write the same output, and the
+	// output matches the manifest of whichever attempt saved it last.
+	keys := make([]string, 0, len(tmp))
+	for key := range tmp {
+		keys = append(keys, key)
+	}
+	sort.Strings(keys)
+
 	data := reducerInput{}
-	for key, values := range tmp {
+	for _, key := range keys {
 		data = append(data, reducerKVs{
 			Key:    key,
-			Values: values,
+			Values: tmp[key],
 		})
 	}
 
@@ -117,47 +243,90 @@ func reduceReader(in *datatypes.InputChunk) (io.Reader, error) {
 	return bytes.NewReader(out), nil
 }
 
+type mapInput struct {
+	Key    string `json:"key"`
+	Value  string `json:"value"`
+	Tag    string `json:"tag,omitempty"`
+	Header string `json:"header,omitempty"`
+}
+
 type kv struct {
 	Key   string      `json:"key"`
 	Value interface{} `json:"value"`
 }
 
-type mapOutputFormat struct {
-	Partitions map[string][]kv `json:"partitions"`
+// streamHeader is the first line of the streamed output of a payload.
+type streamHeader struct {
+	ProtocolVersion int `json:"protocol_version"`
+}
+
+// mapOutputRecord is a single line of the streamed map output, holding one
+// key-value pair and the partition it belongs to.
+type mapOutputRecord struct {
+	Partition int         `json:"partition"`
+	Key       string      `json:"key"`
+	Value     interface{} `json:"value"`
 }
 
 // saveResults takes in the bytes of the output, interprets them, and saves
-// them into required output files.
-func saveResults(in []byte, task *datatypes.Task) error {
+// them into required output files. manifestPath is where the payload wrote
+// the manifest of a reduce output.
+func saveResults(in []byte, task *datatypes.Task, manifestPath string) error {
 	switch task.GetKind() {
 	case datatypes.TaskKind_MAP:
 		return saveMapResults(in, task.GetOutputFiles())
 	case datatypes.TaskKind_REDUCE:
-		return saveReduceResults(in, task.GetOutputFiles())
+		return saveReduceResults(in, task.GetOutputFiles(), manifestPath)
 	}
 
 	return errors.New("task type not valid")
 }
 
+// saveMapResults splits the streamed map output into a file per partition.
+// The payload writes a header line followed by one record per line.
 func saveMapResults(in []byte, output []string) error {
-	data := &mapOutputFormat{}
-	if err := json.Unmarshal(in, &data); err != nil {
-		return errors.Wrap(err, "unable to parse JSON")
+	dec := json.NewDecoder(bytes.NewReader(in))
+	header := &streamHeader{}
+	if err := dec.Decode(header); err != nil {
+		return errors.Wrap(err, "unable to parse output header")
+	}
+	if header.ProtocolVersion != protocolVersion {
+		return errors.Errorf("payload uses protocol version %d, expected %d", header.ProtocolVersion, protocolVersion)
 	}
 
-	for partitionName, kvPairs := range data.Partitions {
-		partition, err := strconv.Atoi(partitionName)
-		if err != nil {
-			return errors.Wrap(err, "unable to convert partition name")
+	partitions := make([][]kv, len(output))
+	for {
+		record := mapOutputRecord{}
+		if err := dec.Decode(&record); err == io.EOF {
+			break
+		} else if err != nil {
+			return errors.Wrap(err, "unable to parse output record")
+		}
+		if record.Partition < 0 || record.Partition >= len(output) {
+			return errors.Errorf("partition %d out of range", record.Partition)
 		}
+		partitions[record.Partition] = append(partitions[record.Partition], kv{
+			Key:   record.Key,
+			Value: record.Value,
+		})
+	}
 
+	// Every partition gets a file, even if it is empty, as the reduce tasks
+	// expect an input file from every map task.
+	for partition, kvPairs := range partitions {
+		if kvPairs == nil {
+			kvPairs = []kv{}
+		}
 		filePath := output[partition]
 		log.Info(filePath)
 		pairsBytes, err := json.Marshal(kvPairs)
 		if err != nil {
 			return errors.Wrap(err, "unable to reserialize pairs")
 		}
-		if err := ioutil.WriteFile(filePath, pairsBytes, 0644); err != nil {
+		if err := os.MkdirAll(filepath.Dir(filePath), 0755); err != nil {
+			return errors.Wrap(err, "unable to create the output directory")
+		}
+		if err := writeFileAtomic(filePath, pairsBytes); err != nil {
 			return errors.Wrap(err, "unable to write the file")
 		}
 	}
@@ -169,7 +338,18 @@ type reducerOutputFormat []struct {
 	Values []interface{} `json:"values"`
 }
 
-func saveReduceResults(in []byte, outputFiles []string) error {
+// outputManifest is the part of the manifest of a reduce output the worker
+// needs. The output itself may not be JSON, so its protocol version is
+// recorded in the manifest instead.
+type outputManifest struct {
+	ProtocolVersion int `json:"protocol_version"`
+}
+
+// saveReduceResults saves the reduce output, and then the manifest written by
+// the payload next to it. The manifest is only put next to the output once the
+// output is in place, so a manifest never describes output which isn't there
+// yet.
+func saveReduceResults(in []byte, outputFiles []string, manifestPath string) error {
 	// TODO: probably add some logic to divide the output keys to their
 	// 		 respectable output files.
 
@@ -177,5 +357,44 @@ func saveReduceResults(in []byte, outputFiles []string) error {
 		return errors.New("output files cannot be empty")
 	}
 
-	return ioutil.WriteFile(outputFiles[0], in, 0644)
+	manifest, err := ioutil.ReadFile(manifestPath)
+	if err != nil {
+		return errors.Wrap(err, "unable to read manifest")
+	}
+	data := &outputManifest{}
+	if err := json.Unmarshal(manifest, data); err != nil {
+		return errors.Wrap(err, "unable to parse manifest")
+	}
+	if data.ProtocolVersion != protocolVersion {
+		return errors.Errorf("payload uses protocol version %d, expected %d", data.ProtocolVersion, protocolVersion)
+	}
+
+	if err := writeFileAtomic(outputFiles[0], in); err != nil {
+		return err
+	}
+	return errors.Wrap(writeFileAtomic(outputFiles[0]+manifestSuffix, manifest), "unable to write manifest")
+}
+
+// writeFileAtomic writes a file by renaming a temporary file into place, so
+// readers never see a partially written file. This also keeps concurrent
+// attempts at the same task from interleaving their output.
+func writeFileAtomic(filePath string, data []byte) error {
+	tmp, err := ioutil.TempFile(filepath.Dir(filePath), filepath.Base(filePath)+".tmp")
+	if err != nil {
+		return errors.Wrap(err, "unable to create temporary file")
+	}
+	defer os.Remove(tmp.Name())
+
+	if _, err := tmp.Write(data); err != nil {
+		tmp.Close()
+		return errors.Wrap(err, "unable to write temporary file")
+	}
+	if err := tmp.Chmod(0644); err != nil {
+		tmp.Close()
+		return errors.Wrap(err, "unable to set file permissions")
+	}
+	if err := tmp.Close(); err != nil {
+		return errors.Wrap(err, "unable to close temporary file")
+	}
+	return errors.Wrap(os.Rename(tmp.Name(), filePath), "unable to move file into place")
 }
@@ -0,0 +1,168 @@
+package runner
+
+import (
+	"encoding/json"
+	"io/ioutil"
+	"os"
+	"path/filepath"
+	"reflect"
+	"testing"
+
+	"github.com/cpssd/heracles/proto/datatypes"
+)
+
+func TestReduceReaderSortsKeys(t *testing.T) {
+	f, err := ioutil.TempFile("", "input")
+	if err != nil {
+		t.Fatalf("unable to create the temporary file: %v", err)
+	}
+	defer os.Remove(f.Name())
+	f.WriteString(`[{"key":"wood","value":1},{"key":"chuck","value":2},{"key":"how","value":3},{"key":"chuck","value":4}]`)
+	f.Close()
+
+	in, err := reduceReader(&datatypes.InputChunk{Path: f.Name()})
+	if err != nil {
+		t.Fatalf("was not expecting an error, got %v", err)
+	}
+	buf, err := ioutil.ReadAll(in)
+	if err != nil {
+		t.Fatalf("unable to read reduce input: %v", err)
+	}
+	data := reducerInput{}
+	if err := json.Unmarshal(buf, &data); err != nil {
+		t.Fatalf("unable to parse reduce input: %v", err)
+	}
+
+	keys := []string{}
+	for _, kvs := range data {
+		keys = append(keys, kvs.Key)
+	}
+	if expected := []string{"chuck", "how", "wood"}; !reflect.DeepEqual(expected, keys) {
+		t.Errorf("expected keys %v, got %v", expected, keys)
+	}
+	if len(data[0].Values) != 2 {
+		t.Errorf("expected 2 values for key %s, got %v", data[0].Key, data[0].Values)
+	}
+}
+
+func TestSaveMapResults(t *testing.T) {
+	dir, err := ioutil.TempDir("", "output")
+	if err != nil {
+		t.Fatalf("unable to create the temporary directory: %v", err)
+	}
+	defer os.RemoveAll(dir)
+
+	output := []string{
+		filepath.Join(dir, "partition_0"),
+		filepath.Join(dir, "partition_1"),
+		filepath.Join(dir, "partition_2"),
+	}
+	in := []byte(`{"protocol_version":2}
+{"partition":1,"key":"foo","value":"bar"}
+{"partition":0,"key":"baz","value":1}
+{"partition":1,"key":"foo","value":"qux"}
+`)
+
+	if err := saveMapResults(in, output); err != nil {
+		t.Fatalf("was not expecting an error, got %v", err)
+	}
+	expected := []string{
+		`[{"key":"baz","value":1}]`,
+		`[{"key":"foo","value":"bar"},{"key":"foo","value":"qux"}]`,
+		`[]`,
+	}
+	for partition, filePath := range output {
+		saved, err := ioutil.ReadFile(filePath)
+		if err != nil {
+			t.Fatalf("unable to read partition %d: %v", partition, err)
+		}
+		if string(saved) != expected[partition] {
+			t.Errorf("expected partition %d to be %s, got %s", partition, expected[partition], saved)
+		}
+	}
+
+	badInputs := [][]byte{
+		[]byte(`{"protocol_version":1}` + "\n"),
+		[]byte(`{"protocol_version":2}` + "\n" + `{"partition":3,"key":"foo","value":"bar"}` + "\n"),
+		[]byte(`{"protocol_version":2}` + "\n" + `{"partition":0,"key":"foo"`),
+	}
+	for _, in := range badInputs {
+		if err := saveMapResults(in, output); err == nil {
+			t.Errorf("%s: error was expected, but got none", in)
+		}
+	}
+}
+
+func TestSaveReduceResults(t *testing.T) {
+	dir, err := ioutil.TempDir("", "output")
+	if err != nil {
+		t.Fatalf("unable to create the temporary directory: %v", err)
+	}
+	defer os.RemoveAll(dir)
+
+	manifestPath := filepath.Join(dir, "payload_manifest")
+	manifest := []byte(`{"protocol_version":2,"record_count":2}`)
+	if err := ioutil.WriteFile(manifestPath, manifest, 0644); err != nil {
+		t.Fatalf("unable to write manifest: %v", err)
+	}
+	outputPath := filepath.Join(dir, "output")
+	output := []byte("foo\t1\nbar\t2\n")
+
+	if err := saveReduceResults(output, []string{outputPath}, manifestPath); err != nil {
+		t.Fatalf("was not expecting an error, got %v", err)
+	}
+	saved, err := ioutil.ReadFile(outputPath)
+	if err != nil {
+		t.Fatalf("unable to read saved output: %v", err)
+	}
+	if string(saved) != string(output) {
+		t.Errorf("expected output %s, got %s", output, saved)
+	}
+	saved, err = ioutil.ReadFile(outputPath + manifestSuffix)
+	if err != nil {
+		t.Fatalf("unable to read saved manifest: %v", err)
+	}
+	if string(saved) != string(manifest) {
+		t.Errorf("expected manifest %s, got %s", manifest, saved)
+	}
+
+	if err := saveReduceResults(output, nil, manifestPath); err == nil {
+		t.Error("error was expected, but got none")
+	}
+
+	oldManifest := []byte(`{"protocol_version":1,"record_count":2}`)
+	if err := ioutil.WriteFile(manifestPath, oldManifest, 0644); err != nil {
+		t.Fatalf("unable to write manifest: %v", err)
+	}
+	otherOutputPath := filepath.Join(dir, "other_output")
+	if err := saveReduceResults(output, []string{otherOutputPath}, manifestPath); err == nil {
+		t.Error("error was expected, but got none")
+	}
+	if _, err := os.Stat(otherOutputPath); !os.IsNotExist(err) {
+		t.Errorf("output of an old payload was saved")
+	}
+}
+
+func TestCheckSanityOutput(t *testing.T) {
+	testCases := []struct {
+		out        string
+		expectGood bool
+	}{
+		{"sanity located\nprotocol_version 2\n", true},
+		{"sanity located\n", false},
+		{"sanity located", false},
+		{"sanity located\nprotocol_version 1\n", false},
+		{"sanity located\nprotocol_version two\n", false},
+		{"something else\nprotocol_version 2\n", false},
+	}
+
+	for _, test := range testCases {
+		err := checkSanityOutput(test.out)
+		if test.expectGood && err != nil {
+			t.Errorf("%q: error was not expected, but got: %v", test.out, err)
+		}
+		if !test.expectGood && err == nil {
+			t.Errorf("%q: error was expected, but got none", test.out)
+		}
+	}
+}
@@ -31,8 +31,12 @@ func Init() error {
 }
 
 func setDefaults() {
-	settings.SetDefault("broker.queue_name", "heracles_tasks")
+	// Must match the queue name of the manager. It differs from the
+	// heracles_tasks queue used before tasks had priorities, as a queue can't
+	// be redeclared with different arguments.
+	settings.SetDefault("broker.queue_name", "heracles_priority_tasks")
 	settings.SetDefault("broker.address", "")
+	settings.SetDefault("broker.max_priority", 10)
 	settings.SetDefault("state.backend", "file")
 	settings.SetDefault("state.location", "")
 }
@@ -40,6 +44,7 @@ func setDefaults() {
 func setOptions() {
 	flag.String("broker.queue_name", "", "queue name")
 	flag.String("broker.address", "", "address of the broker")
+	flag.Int("broker.max_priority", 0, "highest task priority, must match the manager")
 	flag.String("state.backend", "", "backend of the state store")
 	flag.String("state.location", "", "path to the file store")
 
@@ -60,6 +65,11 @@ func GetString(key string) string {
 	return settings.GetString(key)
 }
 
+// GetInt setting
+func GetInt(key string) int {
+	return settings.GetInt(key)
+}
+
 // Set a value in the settings
 func Set(key string, value interface{}) {
 	settings.Set(key, value)
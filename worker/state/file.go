@@ -14,11 +14,16 @@ import (
 
 const (
 	jobsDir          = "jobs"
+	jobSaveFile      = "request"
 	tasksDir         = "tasks"
 	pendingMapDir    = "pending_map_tasks"
 	pendingReduceDir = "pending_reduce_tasks"
 )
 
+// ErrSuperseded is returned when saving an attempt at a task which is already
+// done, or which the manager has given up on.
+var ErrSuperseded = errors.New("task attempt superseded")
+
 // FileStore implements State
 type FileStore struct {
 	path string
@@ -40,6 +45,11 @@ func (f FileStore) SaveProgress(task *datatypes.Task) error {
 	if _, err := os.Stat(taskFilePath); os.IsNotExist(err) {
 		return errors.Wrap(err, "missing task")
 	}
+	if superseded, err := f.Superseded(task); err != nil {
+		return err
+	} else if superseded {
+		return ErrSuperseded
+	}
 
 	var pendingFilePath string
 
@@ -50,7 +60,9 @@ func (f FileStore) SaveProgress(task *datatypes.Task) error {
 		pendingFilePath = path.Join(jobDirPath, pendingReduceDir, id)
 	}
 
-	if _, err := os.Stat(pendingFilePath); os.IsNotExist(err) {
+	// The pending files of a cancelled job are removed by the manager.
+	cancelled := task.GetStatus() == datatypes.TaskStatus_TASK_CANCELLED
+	if _, err := os.Stat(pendingFilePath); os.IsNotExist(err) && !cancelled {
 		return errors.Wrap(err, "missing pending file")
 	}
 
@@ -69,8 +81,60 @@ func (f FileStore) SaveProgress(task *datatypes.Task) error {
 			return errors.Wrapf(err, "unable to remove pending task %s", task.GetId())
 		}
 	}
+	if cancelled {
+		log.V(1).Infof("removing task %s because its cancelled", task.GetId())
+		if err := os.Remove(pendingFilePath); err != nil && !os.IsNotExist(err) {
+			return errors.Wrapf(err, "unable to remove pending task %s", task.GetId())
+		}
+	}
 
 	log.V(1).Infof("successfully saved task %s", task.GetId())
 
 	return nil
 }
+
+// JobCancelled implementation
+func (f FileStore) JobCancelled(jobID string) (bool, error) {
+	buf, err := ioutil.ReadFile(path.Join(f.path, jobsDir, jobID, jobSaveFile))
+	if err != nil {
+		return false, errors.Wrapf(err, "unable to read job %s", jobID)
+	}
+
+	job := &datatypes.Job{}
+	if err := proto.Unmarshal(buf, job); err != nil {
+		return false, errors.Wrapf(err, "unable to parse job %s", jobID)
+	}
+	return job.GetStatus() == datatypes.JobStatus_JOB_CANCELLED, nil
+}
+
+// Superseded implementation
+func (f FileStore) Superseded(task *datatypes.Task) (bool, error) {
+	buf, err := ioutil.ReadFile(path.Join(f.path, jobsDir, task.GetJobId(), tasksDir, task.GetId()))
+	if err != nil {
+		return false, errors.Wrapf(err, "unable to read task %s", task.GetId())
+	}
+
+	saved := &datatypes.Task{}
+	if err := proto.Unmarshal(buf, saved); err != nil {
+		return false, errors.Wrapf(err, "unable to parse task %s", task.GetId())
+	}
+	return superseded(saved, task), nil
+}
+
+// superseded checks whether the saved state of a task rules out any further
+// work by the given attempt at it. This is the case once another attempt has
+// finished the task, once the manager has cancelled the task because its job
+// stopped running, or once the manager has recorded the attempt as failed, for
+// example because it timed out.
+func superseded(saved, task *datatypes.Task) bool {
+	switch saved.GetStatus() {
+	case datatypes.TaskStatus_TASK_DONE, datatypes.TaskStatus_TASK_CANCELLED:
+		return true
+	}
+	for _, attempt := range saved.GetPreviousAttempts() {
+		if attempt.GetAttempt() == task.GetAttempt() {
+			return true
+		}
+	}
+	return false
+}
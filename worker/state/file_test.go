@@ -6,15 +6,18 @@ import (
 	"path"
 	"testing"
 
+	"github.com/golang/protobuf/proto"
+
 	"github.com/cpssd/heracles/proto/datatypes"
 )
 
 func setup(testPath string, taskID string) {
 	tasksDirPath := path.Join(testPath, tasksDir)
 	os.MkdirAll(tasksDirPath, 0777)
+	serializedTask, _ := proto.Marshal(&datatypes.Task{Id: taskID})
 	ioutil.WriteFile(
 		path.Join(tasksDirPath, taskID),
-		[]byte(taskID),
+		serializedTask,
 		0644,
 	)
 
@@ -74,3 +77,79 @@ func TestFileStore(t *testing.T) {
 		t.Error("pending map file should not exist")
 	}
 }
+
+func TestJobCancelled(t *testing.T) {
+	testDir, err := ioutil.TempDir("", "heracles_worker_state_test")
+	if err != nil {
+		t.Errorf("unable to create test directory: %v", err)
+	}
+	defer cleanup(testDir)
+
+	st, err := NewFileStore(testDir)
+	if err != nil {
+		t.Errorf("expected no error, got %v", err)
+	}
+
+	jobID := "test_job"
+	jobDirPath := path.Join(testDir, jobsDir, jobID)
+	os.MkdirAll(jobDirPath, 0777)
+
+	for _, status := range []datatypes.JobStatus{
+		datatypes.JobStatus_JOB_IN_PROGRESS,
+		datatypes.JobStatus_JOB_CANCELLED,
+	} {
+		serializedJob, err := proto.Marshal(&datatypes.Job{Id: jobID, Status: status})
+		if err != nil {
+			t.Fatalf("unable to serialize job: %v", err)
+		}
+		ioutil.WriteFile(path.Join(jobDirPath, jobSaveFile), serializedJob, 0644)
+
+		cancelled, err := st.JobCancelled(jobID)
+		if err != nil {
+			t.Errorf("expected no error, got %v", err)
+		}
+		if expected := status == datatypes.JobStatus_JOB_CANCELLED; cancelled != expected {
+			t.Errorf("job with status %s: expected cancelled to be %t", status, expected)
+		}
+	}
+}
+
+func TestSuperseded(t *testing.T) {
+	task := &datatypes.Task{Attempt: 2, Status: datatypes.TaskStatus_TASK_IN_PROGRESS}
+
+	testCases := []struct {
+		saved    *datatypes.Task
+		expected bool
+	}{
+		{
+			&datatypes.Task{Attempt: 2, Status: datatypes.TaskStatus_TASK_IN_PROGRESS},
+			false,
+		},
+		{
+			&datatypes.Task{Attempt: 3, Status: datatypes.TaskStatus_TASK_IN_PROGRESS, Speculative: true},
+			false,
+		},
+		{
+			&datatypes.Task{Attempt: 3, Status: datatypes.TaskStatus_TASK_DONE, Speculative: true},
+			true,
+		},
+		{
+			&datatypes.Task{Attempt: 2, Status: datatypes.TaskStatus_TASK_CANCELLED},
+			true,
+		},
+		{
+			&datatypes.Task{
+				Attempt:          3,
+				Status:           datatypes.TaskStatus_TASK_PENDING,
+				PreviousAttempts: []*datatypes.TaskAttempt{{Attempt: 2}},
+			},
+			true,
+		},
+	}
+
+	for _, test := range testCases {
+		if got := superseded(test.saved, task); got != test.expected {
+			t.Errorf("saved task %+v: expected superseded to be %t, got %t", test.saved, test.expected, got)
+		}
+	}
+}
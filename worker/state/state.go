@@ -11,6 +11,11 @@ import (
 type State interface {
 	// Save a task in the state
 	SaveProgress(*datatypes.Task) error
+	// JobCancelled checks whether the job with the given ID was cancelled.
+	JobCancelled(jobID string) (bool, error)
+	// Superseded checks whether the task was already finished by another
+	// attempt, or the attempt was given up on by the manager.
+	Superseded(*datatypes.Task) (bool, error)
 }
 
 // New returns a new state store